@@ -0,0 +1,621 @@
+// Render the badges ourselves, instead of relying on shields.io
+
+extern crate iron;
+extern crate rustc_serialize;
+
+use iron::Url as iUrl;
+use rustc_serialize::base64::{ToBase64, STANDARD};
+
+use helpers::{xml_escape, query_param};
+
+// The width of the printable ASCII characters (from space to tilde) in
+// Verdana at 11px – the font the badges are set in. Anything else, like
+// umlauts or emoji, is assumed to be as wide as `WIDE_CHAR`.
+static CHAR_WIDTHS: [f64; 95] = [3.87, 4.33, 5.05, 9.0, 6.99, 11.84, 7.99, 2.95, 4.99, 4.99, 6.99, 9.0,
+                                 4.0, 4.99, 4.0, 4.99, 6.99, 6.99, 6.99, 6.99, 6.99, 6.99, 6.99, 6.99,
+                                 6.99, 6.99, 4.99, 4.99, 9.0, 9.0, 9.0, 6.0, 11.0, 7.52, 7.54, 7.68,
+                                 8.48, 6.96, 6.32, 8.53, 8.27, 4.63, 5.0, 7.62, 6.12, 9.27, 8.23, 8.66,
+                                 6.63, 8.66, 7.65, 7.52, 6.78, 8.05, 7.52, 10.88, 7.54, 6.77, 7.54,
+                                 4.99, 4.99, 4.99, 9.0, 6.99, 6.99, 6.61, 6.85, 5.73, 6.85, 6.55, 3.87,
+                                 6.85, 6.96, 3.02, 3.79, 6.51, 3.02, 10.7, 6.96, 6.68, 6.85, 6.85,
+                                 4.69, 5.73, 4.33, 6.96, 6.51, 8.98, 6.51, 6.51, 5.78, 6.98, 4.99,
+                                 6.98, 9.0];
+static WIDE_CHAR: f64 = 11.0;
+
+// Logos are squares of this size, separated from the label by a small gap
+static LOGO_SIZE: f64 = 14.0;
+static LOGO_GAP: f64 = 3.0;
+
+// The logos we know by name, all others have to be given as `data:` URI
+static LOGOS: [(&'static str, &'static str); 2] = [("github", include_str!("logos/github.svg")),
+                                                   ("rust", include_str!("logos/rust.svg"))];
+
+// The named colors of shields.io, so the color names used
+// throughout the service keep looking the way they did.
+static NAMED_COLORS: [(&'static str, &'static str); 17] = [("brightgreen", "#4c1"),
+                                                           ("green", "#97ca00"),
+                                                           ("yellowgreen", "#a4a61d"),
+                                                           ("yellow", "#dfb317"),
+                                                           ("orange", "#fe7d37"),
+                                                           ("red", "#e05d44"),
+                                                           ("blue", "#007ec6"),
+                                                           ("lightgrey", "#9f9f9f"),
+                                                           ("lightgray", "#9f9f9f"),
+                                                           ("grey", "#555"),
+                                                           ("gray", "#555"),
+                                                           ("success", "#4c1"),
+                                                           ("important", "#fe7d37"),
+                                                           ("critical", "#e05d44"),
+                                                           ("informational", "#007ec6"),
+                                                           ("inactive", "#9f9f9f"),
+                                                           ("blueviolet", "#8a2be2")];
+
+// ## Text Width
+// How wide the text will be when rendered, in pixels
+pub fn text_width(text: &str) -> f64 {
+    text.chars()
+        .map(|c| {
+            match c as usize {
+                code @ 32...126 => CHAR_WIDTHS[code - 32],
+                _ => WIDE_CHAR,
+            }
+        })
+        .fold(0.0, |width, c| width + c)
+}
+
+// ## Color
+// Resolve a color name – or hex code, with or without the leading `#` –
+// to the hex code to fill with. Anything we don't know is light grey.
+pub fn color(name: &str) -> String {
+    if let Some(&(_, hex)) = NAMED_COLORS.iter().find(|&&(known, _)| known == name) {
+        return hex.to_owned();
+    }
+    let hex = name.trim_left_matches('#');
+    match hex.len() {
+        3 | 6 if hex.chars().all(|c| c.is_digit(16)) => format!("#{}", hex),
+        _ => String::from("#9f9f9f"),
+    }
+}
+
+
+// ## RGB
+// The red, green and blue of a hex code, as given by `color`
+pub fn rgb(hex: &str) -> (u8, u8, u8) {
+    let hex = hex.trim_left_matches('#');
+    let channel = |index: usize| {
+        let value = match hex.len() {
+            3 => u8::from_str_radix(&hex[index..index + 1], 16).map(|v| v * 17),
+            _ => u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16),
+        };
+        value.unwrap_or(0)
+    };
+    (channel(0), channel(1), channel(2))
+}
+
+// ## Text Colors
+// The color of the text and of its shadow on the given background:
+// white, unless the color is bright enough to need dark text on top of it.
+pub fn text_colors(background: &str) -> (&'static str, &'static str) {
+    let (red, green, blue) = rgb(background);
+    let brightness = (red as f64 * 299.0 + green as f64 * 587.0 + blue as f64 * 114.0) / 255000.0;
+    if brightness >= 0.69 {
+        ("#333", "#ccc")
+    } else {
+        ("#fff", "#010101")
+    }
+}
+
+// A stop of the gradient laid over the badge: offset, color and opacity
+pub type Stop = (f64, &'static str, f64);
+
+static FLAT_GRADIENT: [Stop; 2] = [(0.0, "#bbb", 0.1), (1.0, "#000", 0.1)];
+static PLASTIC_GRADIENT: [Stop; 4] = [(0.0, "#fff", 0.7),
+                                      (0.1, "#aaa", 0.1),
+                                      (0.9, "#000", 0.3),
+                                      (1.0, "#000", 0.5)];
+
+fn gradient_svg(stops: &[Stop]) -> String {
+    let stops: Vec<String> = stops.iter()
+        .map(|&(offset, color, opacity)| {
+            format!("<stop offset=\"{}\" stop-color=\"{}\" stop-opacity=\"{}\"/>", offset, color, opacity)
+        })
+        .collect();
+    format!("<linearGradient id=\"s\" x2=\"0\" y2=\"100%\">{}</linearGradient>", stops.join(""))
+}
+
+// ## Style
+// The styles shields.io offers, which we replicate
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Style {
+    Flat,
+    FlatSquare,
+    Plastic,
+    ForTheBadge,
+    Social,
+}
+
+impl Style {
+    pub fn parse(name: &str) -> Option<Style> {
+        match name {
+            "flat" => Some(Style::Flat),
+            "flat-square" => Some(Style::FlatSquare),
+            "plastic" => Some(Style::Plastic),
+            "for-the-badge" => Some(Style::ForTheBadge),
+            "social" => Some(Style::Social),
+            _ => None,
+        }
+    }
+}
+
+// ## Metrics
+// The measures the styles differ in – all but `social`, which is
+// drawn differently altogether. Texts are drawn at ten times the size and
+// scaled down, which gives us a precise `textLength` to squeeze them into
+// the width we measured, no matter which font the viewer ends up using.
+pub struct Metrics {
+    pub height: f64,
+    // in tenth of pixels, as the text is scaled down
+    pub font_size: u32,
+    // relative to the Verdana at 11px we have the widths of
+    pub width_factor: f64,
+    pub letter_spacing: f64,
+    pub padding: f64,
+    pub radius: f64,
+    pub baseline: f64,
+    pub shadow: bool,
+    // the gradient laid over the badge
+    pub gradient: &'static [Stop],
+    pub uppercase: bool,
+    pub bold_message: bool,
+}
+
+fn metrics(style: Style) -> Metrics {
+    let flat = Metrics {
+        height: 20.0,
+        font_size: 110,
+        width_factor: 1.0,
+        letter_spacing: 0.0,
+        padding: 5.0,
+        radius: 3.0,
+        baseline: 14.0,
+        shadow: true,
+        gradient: &FLAT_GRADIENT,
+        uppercase: false,
+        bold_message: false,
+    };
+    match style {
+        Style::FlatSquare => {
+            Metrics {
+                radius: 0.0,
+                shadow: false,
+                gradient: &[],
+                ..flat
+            }
+        }
+        Style::Plastic => {
+            Metrics {
+                height: 18.0,
+                radius: 4.0,
+                baseline: 13.0,
+                gradient: &PLASTIC_GRADIENT,
+                ..flat
+            }
+        }
+        Style::ForTheBadge => {
+            Metrics {
+                height: 28.0,
+                font_size: 100,
+                width_factor: 10.0 / 11.0,
+                letter_spacing: 1.25,
+                padding: 12.0,
+                radius: 0.0,
+                baseline: 17.5,
+                shadow: false,
+                gradient: &[],
+                uppercase: true,
+                bold_message: true,
+                ..flat
+            }
+        }
+        _ => flat,
+    }
+}
+
+// How wide the text is set in the given metrics
+fn measure(metrics: &Metrics, text: &str, bold: bool) -> f64 {
+    let width = text_width(text) * metrics.width_factor + metrics.letter_spacing * text.chars().count() as f64;
+    (if bold { width * 1.1 } else { width }).round()
+}
+
+// ## Layout
+// Where everything goes on a badge: its two halves, the texts centered
+// within them – and in front of the label, the logo.
+pub struct Layout {
+    pub metrics: Metrics,
+    pub label: String,
+    pub message: String,
+    pub logo_width: f64,
+    pub label_width: f64,
+    pub message_width: f64,
+    pub label_text_width: f64,
+    pub message_text_width: f64,
+    pub label_color: String,
+    pub message_color: String,
+}
+
+impl Layout {
+    pub fn width(&self) -> f64 {
+        self.label_width + self.message_width
+    }
+
+    pub fn label_center(&self) -> f64 {
+        self.metrics.padding + self.logo_width + self.label_text_width / 2.0
+    }
+
+    pub fn message_center(&self) -> f64 {
+        self.label_width + self.message_width / 2.0
+    }
+}
+
+// ## Badge
+// A badge has a label on the left, in grey, and a message on the right,
+// in the color of the status. Either may be left empty – and a logo can
+// be put in front of the label.
+pub struct Badge {
+    pub label: String,
+    pub message: String,
+    pub color: String,
+    pub label_color: String,
+    pub style: Style,
+    pub logo: Option<String>,
+}
+
+impl Badge {
+    pub fn new(label: &str, message: &str, color: &str) -> Badge {
+        Badge {
+            label: label.to_owned(),
+            message: message.to_owned(),
+            color: color.to_owned(),
+            label_color: String::from("grey"),
+            style: Style::Flat,
+            logo: None,
+        }
+    }
+
+    // ### Customize
+    // Apply the query parameters shields.io supports to the badge: `style`,
+    // `label`, `labelColor` and `color` – or `colorA` and `colorB`, as they
+    // used to be called – and `logo`, given as `data:image/...` URI or by the
+    // name of one of the few logos we ship. Any other we say we don't know.
+    pub fn customize(&mut self, url: &iUrl) -> Result<(), String> {
+        if let Some(style) = query_param(url, "style").and_then(|style| Style::parse(&style)) {
+            self.style = style;
+        }
+        if let Some(label) = query_param(url, "label") {
+            self.label = label;
+        }
+        if let Some(label_color) = query_param(url, "labelColor").or(query_param(url, "colorA")) {
+            self.label_color = label_color;
+        }
+        if let Some(color) = query_param(url, "color").or(query_param(url, "colorB")) {
+            self.color = color;
+        }
+        if let Some(logo) = query_param(url, "logo") {
+            if logo.starts_with("data:image/") {
+                self.logo = Some(logo);
+            } else {
+                match LOGOS.iter().find(|&&(name, _)| name == logo) {
+                    Some(&(_, svg)) => {
+                        self.logo = Some(format!("data:image/svg+xml;base64,{}", svg.as_bytes().to_base64(STANDARD)))
+                    }
+                    None => {
+                        let names: Vec<&str> = LOGOS.iter().map(|&(name, _)| name).collect();
+                        return Err(format!("No logo named {}, try one of {}", logo, names.join(", ")));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // ### Render
+    // Render the badge as SVG, in its style
+    pub fn render(&self) -> String {
+        match self.style {
+            Style::Social => self.render_social(),
+            _ => self.render_plain(),
+        }
+    }
+
+    fn aria_label(&self) -> String {
+        if self.label.is_empty() {
+            xml_escape(&self.message)
+        } else {
+            xml_escape(&format!("{}: {}", self.label, self.message))
+        }
+    }
+
+    fn logo_image(&self, x: f64, y: f64) -> String {
+        match self.logo {
+            Some(ref logo) => {
+                format!("<image x=\"{}\" y=\"{}\" width=\"{size}\" height=\"{size}\" xlink:href=\"{}\"/>",
+                        x,
+                        y,
+                        xml_escape(logo),
+                        size = LOGO_SIZE)
+            }
+            None => String::new(),
+        }
+    }
+
+    // ### Layout
+    // Measure the badge out in the plain styles – the flat, flat-square,
+    // plastic and for-the-badge styles only differ in their metrics. Social
+    // badges are laid out flat here, which is how we rasterize them.
+    pub fn layout(&self) -> Layout {
+        let metrics = metrics(self.style);
+        let (label, message) = if metrics.uppercase {
+            (self.label.to_uppercase(), self.message.to_uppercase())
+        } else {
+            (self.label.clone(), self.message.clone())
+        };
+        let label_text_width = measure(&metrics, &label, false);
+        let message_text_width = measure(&metrics, &message, metrics.bold_message);
+
+        // The logo goes in front of the label, if there is none, we leave out
+        // that half of the badge altogether.
+        let logo_width = match (&self.logo, label.is_empty()) {
+            (&Some(_), true) => LOGO_SIZE,
+            (&Some(_), false) => LOGO_SIZE + LOGO_GAP,
+            (&None, _) => 0.0,
+        };
+        let label_width = if label.is_empty() && self.logo.is_none() {
+            0.0
+        } else {
+            logo_width + label_text_width + 2.0 * metrics.padding
+        };
+        let message_width = message_text_width + 2.0 * metrics.padding;
+
+        Layout {
+            label: label,
+            message: message,
+            logo_width: logo_width,
+            label_width: label_width,
+            message_width: message_width,
+            label_text_width: label_text_width,
+            message_text_width: message_text_width,
+            label_color: color(&self.label_color),
+            message_color: color(&self.color),
+            metrics: metrics,
+        }
+    }
+
+    fn render_plain(&self) -> String {
+        let layout = self.layout();
+        let metrics = &layout.metrics;
+        let width = layout.width();
+        let height = metrics.height;
+
+        let text = |content: &str, center: f64, length: f64, background: &str, bold: bool| {
+            let (fill, shadow) = text_colors(background);
+            let weight = if bold { " font-weight=\"bold\"" } else { "" };
+            let content = xml_escape(content);
+            let mut out = String::new();
+            if metrics.shadow {
+                out.push_str(&format!("<text aria-hidden=\"true\" x=\"{}\" y=\"{}\" fill=\"{}\" \
+                                       fill-opacity=\".3\" transform=\"scale(.1)\" textLength=\"{}\"{}>{}</text>",
+                                      center * 10.0,
+                                      (metrics.baseline + 1.0) * 10.0,
+                                      shadow,
+                                      length * 10.0,
+                                      weight,
+                                      content));
+            }
+            out.push_str(&format!("<text x=\"{}\" y=\"{}\" fill=\"{}\" transform=\"scale(.1)\" \
+                                   textLength=\"{}\"{}>{}</text>",
+                                  center * 10.0,
+                                  metrics.baseline * 10.0,
+                                  fill,
+                                  length * 10.0,
+                                  weight,
+                                  content));
+            out
+        };
+
+        let label_texts = if layout.label.is_empty() {
+            String::new()
+        } else {
+            text(&layout.label,
+                 layout.label_center(),
+                 layout.label_text_width,
+                 &layout.label_color,
+                 false)
+        };
+        let message_texts = text(&layout.message,
+                                 layout.message_center(),
+                                 layout.message_text_width,
+                                 &layout.message_color,
+                                 metrics.bold_message);
+        let (gradient, overlay) = if metrics.gradient.is_empty() {
+            (String::new(), String::new())
+        } else {
+            (gradient_svg(metrics.gradient),
+             format!("<rect width=\"{}\" height=\"{}\" fill=\"url(#s)\"/>", width, height))
+        };
+
+        format!("<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+                 width=\"{width}\" height=\"{height}\" role=\"img\" aria-label=\"{aria}\">\
+                 <title>{aria}</title>{gradient}\
+                 <clipPath id=\"r\"><rect width=\"{width}\" height=\"{height}\" rx=\"{radius}\" \
+                 fill=\"#fff\"/></clipPath>\
+                 <g clip-path=\"url(#r)\">\
+                 <rect width=\"{label_width}\" height=\"{height}\" fill=\"{label_color}\"/>\
+                 <rect x=\"{label_width}\" width=\"{message_width}\" height=\"{height}\" fill=\"{color}\"/>\
+                 {overlay}</g>\
+                 <g text-anchor=\"middle\" font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" \
+                 text-rendering=\"geometricPrecision\" font-size=\"{font_size}\">\
+                 {logo}{label_texts}{message_texts}</g></svg>",
+                width = width,
+                height = height,
+                aria = self.aria_label(),
+                gradient = gradient,
+                radius = metrics.radius,
+                label_width = layout.label_width,
+                message_width = layout.message_width,
+                label_color = layout.label_color,
+                color = layout.message_color,
+                overlay = overlay,
+                font_size = metrics.font_size,
+                logo = self.logo_image(metrics.padding, (height - LOGO_SIZE) / 2.0),
+                label_texts = label_texts,
+                message_texts = message_texts)
+    }
+
+    // The social style looks like the buttons of Github: the label – with its
+    // first letter capitalized – on a light button and the message next to
+    // it in a speech bubble. It has no colors.
+    fn render_social(&self) -> String {
+        let padding = 6.0;
+        let label = {
+            let mut chars = self.label.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        };
+        let label_text_width = text_width(&label).round();
+        let message_text_width = text_width(&self.message).round();
+        let logo_width = match (&self.logo, label.is_empty()) {
+            (&Some(_), true) => LOGO_SIZE,
+            (&Some(_), false) => LOGO_SIZE + LOGO_GAP,
+            (&None, _) => 0.0,
+        };
+        let label_width = logo_width + label_text_width + 2.0 * padding;
+        // the bubble starts after the gap its arrow points into
+        let bubble_x = label_width + 6.0;
+        let bubble_width = message_text_width + 2.0 * padding;
+        let width = if self.message.is_empty() { label_width } else { bubble_x + bubble_width };
+
+        let text = |content: &str, center: f64, length: f64| {
+            format!("<text aria-hidden=\"true\" x=\"{0}\" y=\"150\" fill=\"#fff\" transform=\"scale(.1)\" \
+                     textLength=\"{1}\">{2}</text>\
+                     <text x=\"{0}\" y=\"140\" transform=\"scale(.1)\" textLength=\"{1}\">{2}</text>",
+                    center * 10.0,
+                    length * 10.0,
+                    xml_escape(content))
+        };
+        let label_texts = if label.is_empty() {
+            String::new()
+        } else {
+            text(&label, padding + logo_width + label_text_width / 2.0, label_text_width)
+        };
+        let (bubble, message_texts) = if self.message.is_empty() {
+            (String::new(), String::new())
+        } else {
+            (format!("<rect x=\"{0}\" y=\".5\" width=\"{1}\" height=\"19\" rx=\"2\" fill=\"#fafafa\"/>\
+                      <rect x=\"{2}\" y=\"7.5\" width=\".5\" height=\"5\" stroke=\"#fafafa\"/>\
+                      <path d=\"M{0} 6.5l-3 3v1l3 3\" fill=\"#fafafa\"/>",
+                     bubble_x + 0.5,
+                     bubble_width - 1.0,
+                     bubble_x),
+             text(&self.message, bubble_x + bubble_width / 2.0, message_text_width))
+        };
+
+        format!("<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+                 width=\"{width}\" height=\"20\" role=\"img\" aria-label=\"{aria}\">\
+                 <title>{aria}</title>\
+                 <linearGradient id=\"s\" x2=\"0\" y2=\"100%\">\
+                 <stop offset=\"0\" stop-color=\"#fcfcfc\" stop-opacity=\"0\"/>\
+                 <stop offset=\"1\" stop-opacity=\".1\"/></linearGradient>\
+                 <g stroke=\"#d5d5d5\">\
+                 <rect stroke=\"none\" fill=\"#fcfcfc\" x=\".5\" y=\".5\" width=\"{label_box}\" height=\"19\" rx=\"2\"/>\
+                 <rect x=\".5\" y=\".5\" width=\"{label_box}\" height=\"19\" rx=\"2\" fill=\"url(#s)\"/>\
+                 {bubble}</g>{logo}\
+                 <g fill=\"#333\" text-anchor=\"middle\" font-family=\"Helvetica Neue,Helvetica,Arial,sans-serif\" \
+                 text-rendering=\"geometricPrecision\" font-weight=\"700\" font-size=\"110\">\
+                 {label_texts}{message_texts}</g></svg>",
+                width = width,
+                aria = self.aria_label(),
+                label_box = label_width - 1.0,
+                bubble = bubble,
+                logo = self.logo_image(padding, 3.0),
+                label_texts = label_texts,
+                message_texts = message_texts)
+    }
+}
+
+// ## Tests
+// The badges for every style and parameter, compared to the ones we know
+// to be right in `tests/golden`.
+#[cfg(test)]
+mod tests {
+    use iron::Url as iUrl;
+
+    use super::Badge;
+
+    fn render(query: &str) -> String {
+        let mut badge = Badge::new("clippy", "3 warnings", "yellow");
+        let url = iUrl::parse(&format!("http://localhost/badge.svg?{}", query)).unwrap();
+        badge.customize(&url).unwrap();
+        badge.render()
+    }
+
+    #[test]
+    fn flat() {
+        assert_eq!(render("style=flat"), include_str!("../tests/golden/flat.svg"));
+    }
+
+    #[test]
+    fn flat_square() {
+        assert_eq!(render("style=flat-square"), include_str!("../tests/golden/flat-square.svg"));
+    }
+
+    #[test]
+    fn plastic() {
+        assert_eq!(render("style=plastic"), include_str!("../tests/golden/plastic.svg"));
+    }
+
+    #[test]
+    fn for_the_badge() {
+        assert_eq!(render("style=for-the-badge"), include_str!("../tests/golden/for-the-badge.svg"));
+    }
+
+    #[test]
+    fn social() {
+        assert_eq!(render("style=social"), include_str!("../tests/golden/social.svg"));
+    }
+
+    #[test]
+    fn label() {
+        assert_eq!(render("label=lints"), include_str!("../tests/golden/label.svg"));
+    }
+
+    #[test]
+    fn label_color() {
+        assert_eq!(render("labelColor=555"), include_str!("../tests/golden/label-color.svg"));
+    }
+
+    #[test]
+    fn color() {
+        assert_eq!(render("color=ff69b4"), include_str!("../tests/golden/color.svg"));
+    }
+
+    #[test]
+    fn logo() {
+        assert_eq!(render("logo=data:image/png;base64,AAAA"), include_str!("../tests/golden/logo.svg"));
+        assert_eq!(render("style=social&logo=data:image/png;base64,AAAA"),
+                   include_str!("../tests/golden/social-logo.svg"));
+    }
+
+    #[test]
+    fn named_logo() {
+        assert_eq!(render("logo=rust"), include_str!("../tests/golden/named-logo.svg"));
+    }
+
+    #[test]
+    fn unknown_logo() {
+        let mut badge = Badge::new("clippy", "3 warnings", "yellow");
+        let url = iUrl::parse("http://localhost/badge.svg?logo=python").unwrap();
+        assert!(badge.customize(&url).is_err());
+    }
+}
@@ -2,20 +2,157 @@
 
 extern crate rustc_serialize;
 
-use rustc_serialize::json::Json;
+use rustc_serialize::json::{self, Json};
 
 use std::process::{ Command, Output };
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 use std::vec::Vec;
 use std::{env, fs};
 
+use diagnostics::{Diagnostic, Kind, deduplicate, apply_baseline};
+
+// The file, next to the `Cargo.toml`, projects can commit their baseline in.
+// It's a JSON list of fingerprints, as generated by the `baseline.json` method.
+static BASELINE_FILE: &'static str = "clippy-baseline.json";
+
 // Enum describing the State of the Clippy result,
-// whether everything went fine or if warnings or
-// errors were found – and if so, how many
+// whether everything went fine, if warnings or lint
+// errors were found – and if so, how many – or if
+// the crate didn't even compile. If the compiler
+// itself crashed, we keep the panic message.
 pub enum ClippyResult {
     Success,
     WithWarnings(u32),
     WithErrors(u32, u32),
+    BuildFailed(u32),
+    Ice(String),
+}
+
+// ## Clippy Run
+// What `run` found: the result, the diagnostics it was computed from
+// and the version of the toolchain used.
+pub struct ClippyRun {
+    pub result: ClippyResult,
+    pub diagnostics: Vec<Diagnostic>,
+    pub toolchain: Option<String>,
+}
+
+// The `Cargo.lock` we were built from, to know which clippy we ship with
+static CARGO_LOCK: &'static str = include_str!("../Cargo.lock");
+
+// The value of `key` in the `[[package]]` section of clippy in the `Cargo.lock`
+fn locked_clippy(key: &str) -> Option<String> {
+    // Every package is a `[[package]]` section with its name, version and source
+    CARGO_LOCK.split("[[package]]")
+        .find(|package| package.lines().any(|line| line.trim() == "name = \"clippy\""))
+        .and_then(|package| {
+            package.lines()
+                   .map(|line| line.trim())
+                   .find(|line| line.starts_with(key))
+                   .map(|line| line[key.len()..].trim_matches(|c: char| c == ' ' || c == '=' || c == '"').to_owned())
+        })
+}
+
+// ## Clippy Commit
+// The commit of the clippy we are shipping with, as it's a git dependency
+pub fn clippy_commit() -> Option<String> {
+    locked_clippy("source").and_then(|source| {
+        match source.rsplitn(2, '#').next() {
+            Some(commit) if commit.len() >= 7 && !commit.contains('+') => Some(commit.to_owned()),
+            _ => None,
+        }
+    })
+}
+
+// ## Clippy Version
+// The version of the clippy we are shipping with. As it's a git dependency, the
+// most precise thing to know is the commit, which we look up in the `Cargo.lock`.
+pub fn clippy_version() -> Option<String> {
+    locked_clippy("version").map(|version| {
+        match clippy_commit() {
+            Some(commit) => format!("{} ({})", version, &commit[..7]),
+            None => version,
+        }
+    })
+}
+
+// ## Find ICE
+// Look for signs of an internal compiler error in the output: rustc (or clippy)
+// panicking or reporting an "internal compiler error". Neither is the fault of
+// the project being linted, so we want to tell it apart from a failed build.
+// Returns the panic message – or the error message, if there is no panic.
+fn find_ice(stderr: &str) -> Option<String> {
+    let lines: Vec<&str> = stderr.lines().collect();
+    let mut ice_message: Option<String> = None;
+    // Build scripts may panic, too – that's a failed build, not an ICE.
+    let compiler_panicked = stderr.contains("internal compiler error") ||
+                            stderr.contains("unexpectedly panicked");
+
+    for (index, line) in lines.iter().enumerate() {
+        let panic_start = match line.find("panicked at ") {
+            Some(start) if compiler_panicked || line.contains("thread 'rustc'") => Some(start),
+            _ => None,
+        };
+        if let Some(start) = panic_start {
+            let rest = line[start + "panicked at ".len()..].trim();
+            // Older compilers quote the message right here:
+            // `thread 'rustc' panicked at 'message', src/file.rs:1`
+            // newer ones put it on the line after the location.
+            if rest.starts_with('\'') {
+                if let Some(end) = rest.rfind("', ") {
+                    return Some(rest[1..end].to_owned());
+                }
+            }
+            return Some(match lines.get(index + 1).map(|next| next.trim()) {
+                Some(next) if !next.is_empty() => next.to_owned(),
+                _ => rest.to_owned(),
+            });
+        }
+
+        if ice_message.is_none() {
+            // The message might be reported as plain text or as JSON
+            let text = match Json::from_str(line) {
+                Ok(json) => {
+                    match (json.find("level"), json.find("message")) {
+                        (Some(&Json::String(ref level)), Some(&Json::String(ref message))) => {
+                            format!("{}: {}", level, message)
+                        }
+                        _ => String::new(),
+                    }
+                }
+                Err(_) => line.to_string(),
+            };
+            if let Some(start) = text.find("internal compiler error") {
+                let message = text[start + "internal compiler error".len()..]
+                                  .trim_left_matches(|c: char| c == ':' || c.is_whitespace());
+                ice_message = Some(message.to_owned());
+            }
+        }
+    }
+    ice_message
+}
+
+// ## Read Baseline
+// Read the fingerprints of the baseline, if the project has one.
+fn read_baseline<F>(path: &Path, logger: &F) -> Vec<String>
+    where F: Fn(&str)
+{
+    let mut content = String::new();
+    if File::open(path.join(BASELINE_FILE)).and_then(|mut f| f.read_to_string(&mut content)).is_err() {
+        return vec![];
+    }
+    match json::decode::<Vec<String>>(&content) {
+        Ok(baseline) => {
+            logger(&format!("Using baseline of {} entries from {}", baseline.len(), BASELINE_FILE));
+            baseline
+        }
+        Err(error) => {
+            logger(&format!("Ignoring {}, it couldn't be parsed: {}", BASELINE_FILE, error));
+            vec![]
+        }
+    }
 }
 
 fn run_in_sandbox<F>(path: &Path, command: &Vec<&str>, logger: &F) -> Output
@@ -68,13 +205,18 @@ fn run_in_sandbox<F>(path: &Path, command: &Vec<&str>, logger: &F) -> Output
 // ## Run
 // Run clippy in the specified Path. We expect there to be a Cargo.toml file, checking for
 // that should have happened already. While calculating the `ClippyResult`, inform about
-// the current process via the `logger` function.
-pub fn run<F>(path: &Path, logger: F) -> Result<ClippyResult, String>
+// the current process via the `logger` function. Next to the result, the parsed
+// diagnostics are returned, so they can be stored for the reports.
+pub fn run<F>(path: &Path, logger: F) -> Result<ClippyRun, String>
     where F: Fn(&str)
 {
 
-    //  run rustc and cargo versions for easier debugging for the viewer
-    run_in_sandbox(&path, &vec!["rustc", "--version"], &logger);
+    //  run rustc and cargo versions for easier debugging for the viewer,
+    //  we also keep the rustc version for the record
+    let toolchain = String::from_utf8(run_in_sandbox(&path, &vec!["rustc", "--version"], &logger).stdout)
+                        .ok()
+                        .map(|version| version.trim().to_owned())
+                        .and_then(|version| if version.is_empty() { None } else { Some(version) });
     run_in_sandbox(&path, &vec!["cargo", "--version"], &logger);
 
     logger("-------------------------------- Running Clippy");
@@ -106,33 +248,55 @@ pub fn run<F>(path: &Path, logger: F) -> Result<ClippyResult, String>
 
     let stderr = String::from_utf8(output.stderr).unwrap();
 
+    // If the compiler crashed, none of the diagnostics can be trusted
+    if let Some(message) = find_ice(&stderr) {
+        logger(&format!("Internal compiler error: {}", message));
+        return Ok(ClippyRun {
+            result: ClippyResult::Ice(message),
+            diagnostics: vec![],
+            toolchain: toolchain,
+        });
+    }
+
     let mut warnings = 0;
-    let mut errors = 0;
+    let mut lint_errors = 0;
+    let mut compile_errors = 0;
     // Next up, we need to parse the outpuf from stderr, where
     // clippy and the compiler might report errors to us. There is
     // one error per line, which is why we split it into lines. We
     // then use `filter_map` to find all those lines we can decode
-    // from JSON
-    let messages: Vec<String> = stderr.split('\n')
+    // from JSON and turn into a `Diagnostic`. The summary lines
+    // don't tell us anything new, so we skip them.
+    let diagnostics: Vec<Diagnostic> = stderr.split('\n')
         .filter_map(|line| Json::from_str(&line).ok())
-        .filter_map(|json| {
-            // and then `filter_map` those into
-            // the warnings and errors we care about,
-            // while also updating the local count
-            // of both.
-            let obj = json.as_object().unwrap();
-            match obj.get("level") {
-                Some(&Json::String(ref level)) => {
-                    if level == "warning" {
-                        warnings += 1;
-                    } else if level == "error" {
-                        errors += 1;
-                    }
-                    Some(format!("{level}: {msg}", level = level,
-                                 msg = obj.get("message").unwrap()))
-                }
-                _ => None,
+        .filter_map(|json| Diagnostic::from_json(&json))
+        .filter(|diagnostic| diagnostic.kind() != Kind::Summary)
+        .collect();
+
+    // The same diagnostic might have been reported many times,
+    // we only count it once.
+    let raw_count = diagnostics.len();
+    let diagnostics = deduplicate(diagnostics);
+
+    // Everything in the baseline is known and doesn't count
+    let diagnostics = apply_baseline(diagnostics, &read_baseline(path, &logger));
+
+    // and then `map` those into the messages we care
+    // about, while also updating the local count
+    // of new warnings, lint and compile errors.
+    let messages: Vec<String> = diagnostics.iter()
+        .map(|diagnostic| {
+            if diagnostic.baselined {
+                return format!("{level} (baselined): {msg}", level = diagnostic.level,
+                               msg = diagnostic.message);
             }
+            match diagnostic.kind() {
+                Kind::Warning => warnings += 1,
+                Kind::LintError => lint_errors += 1,
+                Kind::CompileError => compile_errors += 1,
+                _ => {}
+            }
+            format!("{level}: {msg}", level = diagnostic.level, msg = diagnostic.message)
         })
         // The collect executes this iterative into
         // a vector of results. We can now log to the
@@ -140,16 +304,37 @@ pub fn run<F>(path: &Path, logger: F) -> Result<ClippyResult, String>
         .collect();
 
     logger(&format!("-----\nMessages identified:\n {}", messages.join("\n")));
+    logger(&format!("{} messages reported, {} unique", raw_count, diagnostics.len()));
 
     // Next parse the count of errors and warnings
-    // and wrap that into the appropriate `ClippyResult`
-    if output.status.success() {
-        match (errors, warnings) {
-            (0, 0) => Ok(ClippyResult::Success),
-            (0, x) => Ok(ClippyResult::WithWarnings(x)),
-            _ => Ok(ClippyResult::WithErrors(errors, warnings))
+    // and wrap that into the appropriate `ClippyResult`.
+    // Any error makes cargo exit with a failure, so we
+    // only consider the run itself failed if there
+    // isn't any error explaining that.
+    let result = match (compile_errors, lint_errors, warnings) {
+        (0, 0, 0) => ClippyResult::Success,
+        (0, 0, x) => ClippyResult::WithWarnings(x),
+        (0, _, _) => ClippyResult::WithErrors(lint_errors, warnings),
+        _ => ClippyResult::BuildFailed(compile_errors),
+    };
+
+    // Denied lints in the baseline don't count, but still fail cargo
+    let baselined_errors = diagnostics.iter()
+                                      .any(|diagnostic| diagnostic.baselined && diagnostic.kind() == Kind::LintError);
+    let unexplained_failure = match result {
+        ClippyResult::Success | ClippyResult::WithWarnings(_) => {
+            !output.status.success() && !baselined_errors
         }
-    } else {
+        _ => false,
+    };
+
+    if unexplained_failure {
         Err("Running Clippy failed.".to_string())
+    } else {
+        Ok(ClippyRun {
+            result: result,
+            diagnostics: diagnostics,
+            toolchain: toolchain,
+        })
     }
 }
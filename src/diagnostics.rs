@@ -0,0 +1,338 @@
+// Structured compiler diagnostics as reported by rustc and clippy via
+// `--error-format=json`. We keep them around after the run, so all the
+// report formats can be generated from them later.
+
+extern crate rustc_serialize;
+
+use rustc_serialize::json::{Json, Object};
+use std::vec::Vec;
+
+// ## Span
+// A single source location a diagnostic points to. If clippy knows how
+// to fix the problem, the span also carries the suggested replacement.
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Span {
+    pub file_name: String,
+    pub byte_start: u64,
+    pub byte_end: u64,
+    pub line_start: u64,
+    pub line_end: u64,
+    pub column_start: u64,
+    pub column_end: u64,
+    pub is_primary: bool,
+    pub label: Option<String>,
+    pub suggested_replacement: Option<String>,
+    pub suggestion_applicability: Option<String>,
+    pub snippet: Option<String>,
+}
+
+// ## Diagnostic
+// One message emitted by the compiler, with the lint that triggered it (if
+// any) – or the error code of rustc, like `E0308` – where it points to and
+// its child notes and help messages. As the
+// same message may be emitted many times (see `deduplicate`), we also keep
+// track of how often we've seen it – and whether it is part of the projects
+// baseline (see `apply_baseline`).
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Diagnostic {
+    pub level: String,
+    pub message: String,
+    pub lint: Option<String>,
+    pub error_code: Option<String>,
+    pub spans: Vec<Span>,
+    pub children: Vec<Diagnostic>,
+    pub occurrences: u32,
+    pub baselined: bool,
+}
+
+// ## Kind
+// What a diagnostic means for the result of the run: the compiler may fail
+// to build the crate at all, clippy may report lints at `deny` or `warn`
+// level – and then there are notes and the final "aborting due to N previous
+// errors" summary, which don't count towards anything.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Kind {
+    CompileError,
+    LintError,
+    Warning,
+    Summary,
+    Note,
+}
+
+// Small accessors to keep the JSON unpacking below readable
+fn string_field(obj: &Object, name: &str) -> Option<String> {
+    match obj.get(name) {
+        Some(&Json::String(ref value)) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn u64_field(obj: &Object, name: &str) -> u64 {
+    obj.get(name).and_then(|value| value.as_u64()).unwrap_or(0)
+}
+
+// The lint name can be found in two places: newer compilers report it as the
+// `code` of the diagnostic, older ones only mention it in a child note like
+// "#[warn(needless_return)] on by default". We strip the `clippy::` tool
+// prefix in either case, so lints are always referred to by their plain name.
+// Plain compiler errors have an error code like `E0308` as `code` instead.
+fn code(obj: &Object) -> Option<String> {
+    match obj.get("code") {
+        Some(&Json::Object(ref code)) => string_field(code, "code"),
+        _ => None,
+    }
+}
+
+fn is_error_code(code: &str) -> bool {
+    code.len() == 5 && code.starts_with('E') && code[1..].chars().all(|c| c.is_digit(10))
+}
+
+fn lint_from_note(message: &str) -> Option<String> {
+    for level in &["#[warn(", "#[deny(", "#[forbid("] {
+        if let Some(start) = message.find(level) {
+            let rest = &message[start + level.len()..];
+            if let Some(end) = rest.find(')') {
+                return Some(rest[..end].to_owned());
+            }
+        }
+    }
+    None
+}
+
+fn strip_tool_prefix(lint: String) -> String {
+    if lint.starts_with("clippy::") {
+        lint["clippy::".len()..].to_owned()
+    } else {
+        lint
+    }
+}
+
+impl Span {
+    fn from_json(obj: &Object) -> Span {
+        Span {
+            file_name: string_field(obj, "file_name").unwrap_or(String::new()),
+            byte_start: u64_field(obj, "byte_start"),
+            byte_end: u64_field(obj, "byte_end"),
+            line_start: u64_field(obj, "line_start"),
+            line_end: u64_field(obj, "line_end"),
+            column_start: u64_field(obj, "column_start"),
+            column_end: u64_field(obj, "column_end"),
+            is_primary: obj.get("is_primary").and_then(|v| v.as_boolean()).unwrap_or(false),
+            label: string_field(obj, "label"),
+            suggested_replacement: string_field(obj, "suggested_replacement"),
+            suggestion_applicability: string_field(obj, "suggestion_applicability"),
+            // the first line of source code the span covers
+            snippet: match obj.get("text") {
+                Some(&Json::Array(ref text)) => {
+                    text.first()
+                        .and_then(|line| line.as_object())
+                        .and_then(|line| string_field(line, "text"))
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Diagnostic {
+    // ### From JSON
+    // Parse a single line of compiler output. Returns `None` if the JSON
+    // doesn't look like a diagnostic (which happens for cargo's own output)
+    pub fn from_json(json: &Json) -> Option<Diagnostic> {
+        let obj = match json.as_object() {
+            Some(obj) => obj,
+            None => return None,
+        };
+        let level = match string_field(obj, "level") {
+            Some(level) => level,
+            None => return None,
+        };
+
+        let spans: Vec<Span> = match obj.get("spans") {
+            Some(&Json::Array(ref spans)) => {
+                spans.iter().filter_map(|span| span.as_object()).map(Span::from_json).collect()
+            }
+            _ => vec![],
+        };
+        let children: Vec<Diagnostic> = match obj.get("children") {
+            Some(&Json::Array(ref children)) => {
+                children.iter().filter_map(Diagnostic::from_json).collect()
+            }
+            _ => vec![],
+        };
+
+        let (lint, error_code) = match code(obj) {
+            Some(code) => {
+                if is_error_code(&code) {
+                    (None, Some(code))
+                } else {
+                    (Some(code), None)
+                }
+            }
+            None => (None, None),
+        };
+        let lint = lint.or_else(|| children.iter().filter_map(|c| lint_from_note(&c.message)).next())
+                       .map(strip_tool_prefix);
+
+        Some(Diagnostic {
+            level: level,
+            message: string_field(obj, "message").unwrap_or(String::new()),
+            lint: lint,
+            error_code: error_code,
+            spans: spans,
+            children: children,
+            occurrences: 1,
+            baselined: false,
+        })
+    }
+
+    // ### Kind
+    // Classify the diagnostic. Errors without a lint are proper compiler
+    // errors (like type errors) and mean the build failed, while errors with
+    // a lint are just lints configured to `deny`.
+    pub fn kind(&self) -> Kind {
+        if self.is_summary() {
+            return Kind::Summary;
+        }
+        match (self.level.as_str(), &self.lint) {
+            ("error", &Some(_)) => Kind::LintError,
+            ("error", &None) => Kind::CompileError,
+            ("warning", _) => Kind::Warning,
+            _ => Kind::Note,
+        }
+    }
+
+    // Summaries like "aborting due to 2 previous errors" or "3 warnings
+    // emitted" don't point anywhere and just repeat what we already know.
+    fn is_summary(&self) -> bool {
+        self.spans.is_empty() && self.lint.is_none() &&
+        (self.message.starts_with("aborting due to") ||
+         self.message.ends_with("warnings emitted") ||
+         self.message.ends_with("warning emitted"))
+    }
+
+    // ### Primary Span
+    // The location the diagnostic is actually about, if it has any.
+    pub fn primary_span(&self) -> Option<&Span> {
+        self.spans.iter().find(|span| span.is_primary).or(self.spans.first())
+    }
+
+    // ### Fingerprint
+    // Identify the diagnostic across commits: other changes to the file move
+    // it to another line, so rather than the line number, we use the line's
+    // code (ignoring whitespace) next to the lint and file.
+    pub fn fingerprint(&self) -> String {
+        let (file_name, code) = match self.primary_span() {
+            Some(span) => {
+                (span.file_name.as_str(),
+                 span.snippet
+                     .as_ref()
+                     .map(|code| code.split_whitespace().collect::<Vec<&str>>().join(" "))
+                     .unwrap_or(self.message.clone()))
+            }
+            None => ("", self.message.clone()),
+        };
+        format!("{}|{}|{}",
+                self.lint.as_ref().map(|lint| lint.as_str()).unwrap_or("rustc"),
+                file_name,
+                code)
+    }
+
+    // Two diagnostics are the same, if the same lint reports the same
+    // message about the same place in the code.
+    fn is_same(&self, other: &Diagnostic) -> bool {
+        let location = |span: &Span| {
+            (span.file_name.clone(), span.line_start, span.column_start, span.line_end, span.column_end)
+        };
+        self.lint == other.lint && self.message == other.message &&
+        self.primary_span().map(&location) == other.primary_span().map(&location)
+    }
+}
+
+// ## Counts
+// The number of warnings and errors – lints and compiler errors alike
+pub fn counts(diagnostics: &[Diagnostic]) -> (u32, u32) {
+    diagnostics.iter().fold((0, 0), |(warnings, errors), diagnostic| {
+        match diagnostic.kind() {
+            Kind::Warning => (warnings + 1, errors),
+            Kind::LintError | Kind::CompileError => (warnings, errors + 1),
+            _ => (warnings, errors),
+        }
+    })
+}
+
+// ## Deduplicate
+// A lint firing inside a macro is reported once for every place the macro is
+// used, and code compiled for several targets is linted once per target – all
+// pointing to the very same span. Merge those into one diagnostic, counting
+// the `occurrences` instead.
+pub fn deduplicate(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let mut unique: Vec<Diagnostic> = vec![];
+    for diagnostic in diagnostics {
+        match unique.iter().position(|known| known.is_same(&diagnostic)) {
+            Some(index) => unique[index].occurrences += diagnostic.occurrences,
+            None => unique.push(diagnostic),
+        }
+    }
+    unique
+}
+
+// ## Apply Baseline
+// Projects can commit a baseline: the fingerprints of all the diagnostics they
+// know about and accept for now. Mark all diagnostics found in there as
+// `baselined`, so only new ones are counted. Just like for the comparison,
+// every fingerprint in the baseline can only be matched once. Only lints can
+// be accepted like that – a crate that doesn't compile always fails.
+pub fn apply_baseline(diagnostics: Vec<Diagnostic>, baseline: &[String]) -> Vec<Diagnostic> {
+    let mut remaining: Vec<&String> = baseline.iter().collect();
+    diagnostics.into_iter()
+               .map(|mut diagnostic| {
+                   match diagnostic.kind() {
+                       Kind::Warning | Kind::LintError => {}
+                       _ => return diagnostic,
+                   }
+                   let fingerprint = diagnostic.fingerprint();
+                   if let Some(index) = remaining.iter().position(|known| **known == fingerprint) {
+                       remaining.remove(index);
+                       diagnostic.baselined = true;
+                   }
+                   diagnostic
+               })
+               .collect()
+}
+
+// ## Comparison
+// The difference between the diagnostics of two runs: which ones the newer
+// run introduced, which ones it fixed and which ones are still there.
+pub struct Comparison {
+    pub introduced: Vec<Diagnostic>,
+    pub fixed: Vec<Diagnostic>,
+    pub unchanged: Vec<Diagnostic>,
+}
+
+// ## Compare
+// Match the diagnostics of `base` and `head` by their fingerprint. The same
+// fingerprint may appear more than once, so each one found in `base` can only
+// be matched once.
+pub fn compare(base: &[Diagnostic], head: &[Diagnostic]) -> Comparison {
+    let mut remaining: Vec<(String, &Diagnostic)> = base.iter()
+                                                        .map(|d| (d.fingerprint(), d))
+                                                        .collect();
+    let mut comparison = Comparison {
+        introduced: vec![],
+        fixed: vec![],
+        unchanged: vec![],
+    };
+    for diagnostic in head {
+        let fingerprint = diagnostic.fingerprint();
+        match remaining.iter().position(|&(ref known, _)| known == &fingerprint) {
+            Some(index) => {
+                remaining.remove(index);
+                comparison.unchanged.push(diagnostic.clone());
+            }
+            None => comparison.introduced.push(diagnostic.clone()),
+        }
+    }
+    comparison.fixed = remaining.into_iter().map(|(_, d)| d.clone()).collect();
+    comparison
+}
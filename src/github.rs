@@ -3,26 +3,55 @@ extern crate redis;
 extern crate time;
 extern crate tempdir;
 extern crate rand;
+extern crate rustc_serialize;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::thread;
 use tempdir::TempDir;
 
 use redis::{Commands, PipelineCommands};
+use rustc_serialize::json;
+use time::{now_utc, precise_time_ns};
 
-use helpers::{setup_redis, log_redis, download_and_unzip};
-use clippy::{ClippyResult, run as run_clippy};
+use helpers::{setup_redis, log_redis, download_and_unzip, set_diagnostics};
+use clippy::{ClippyResult, ClippyRun, clippy_version, run as run_clippy};
+use record::{ClippyRecord, Durations, State};
+use thresholds::Thresholds;
+use patch;
+use waiting;
+
+// A failed run is usually on us – a download that broke off, a full disk – so
+// we forget about it after an hour, to lint the commit again when it's asked
+// for the next time.
+static FAILED_RESULT_SECONDS: usize = 60 * 60;
+
+// ## Outcome
+// Everything we keep from a run: what clippy found, the patch applying its
+// suggestions, where in the repository the crate is, the thresholds to pick
+// the color of the badge with and how long downloading and linting took.
+struct Outcome {
+    run: ClippyRun,
+    fixes: String,
+    prefix: String,
+    thresholds: Thresholds,
+    durations: Durations,
+}
+
+// milliseconds passed since `start`, as given by `precise_time_ns`
+fn millis_since(start: u64) -> Option<u64> {
+    Some((precise_time_ns() - start) / 1_000_000)
+}
 
 // ## Update For Github
 // Given the user, repo and SHA, this function fetches the
 // git repo and runs clippy in the folder containing the `Cargo.toml` file
 // This is the internal function invoked from `schedule_update` in a seperat
-// background thread. It will return an Error or the ClippyResult once done.
+// background thread. It will return an Error or the Outcome once done.
 fn update_for_github<F>(user: &str,
                         repo: &str,
                         sha: &str,
                         logger: F)
-                        -> Result<ClippyResult, String>
+                        -> Result<Outcome, String>
 // One interesting feature of this function (and a few others) is the scoped
 // `logger` which is passed around. During execution this function is invoked
 // multiple times to report on the current state of affairs.
@@ -43,8 +72,10 @@ fn update_for_github<F>(user: &str,
 
 
         logger(&format!("Fetching {}", &github_url));
+        let download_start = precise_time_ns();
         match download_and_unzip(&github_url, &temp_dir) {
             Ok(files) => {
+                let download = millis_since(download_start);
                 // Once unzipped, we report back the files found and try to find the
                 // patch containing the 'cargo.toml' file – this iter stops at the first
                 // item found.
@@ -57,7 +88,31 @@ fn update_for_github<F>(user: &str,
                         let parent_directory = path.parent().unwrap();
                         logger(&format!("Cargo file found in {}",
                                         parent_directory.to_string_lossy().into_owned()));
-                        run_clippy(parent_directory, logger)
+                        // While we still have the sources, apply the fixes clippy
+                        // suggested to them. Github puts everything into a
+                        // `repo-sha` folder, which isn't part of the repository.
+                        let prefix: PathBuf = parent_directory.strip_prefix(temp_dir.path())
+                                                              .unwrap()
+                                                              .components()
+                                                              .skip(1)
+                                                              .map(|c| c.as_os_str())
+                                                              .collect();
+                        let thresholds = Thresholds::for_project(parent_directory, &logger);
+                        let lint_start = precise_time_ns();
+                        run_clippy(parent_directory, logger).map(|run| {
+                            let lint = millis_since(lint_start);
+                            Outcome {
+                                fixes: patch::generate(parent_directory, &prefix, &run.diagnostics),
+                                prefix: prefix.to_string_lossy().into_owned(),
+                                run: run,
+                                thresholds: thresholds,
+                                durations: Durations {
+                                    download: download,
+                                    lint: lint,
+                                    total: None,
+                                },
+                            }
+                        })
                     }
                     // Report back if there is no `Cargo.toml` file or if there has been
                     // any other error during download_and_unzip.
@@ -88,6 +143,9 @@ pub fn schedule_update(user: &str, repo: &str, sha: &str) {
     let result_key = format!("result/{}", base_key).to_owned();
     let lock_key = format!("lock/{}", base_key).to_owned();
     let log_key = format!("log/{}", base_key).to_owned();
+    let diagnostics_key = format!("diagnostics/{}", base_key).to_owned();
+    let patch_key = format!("patch/{}", base_key).to_owned();
+    let prefix_key = format!("prefix/{}", base_key).to_owned();
 
     // now spawn the background thread. We create both the redis connection
     // and the logger clojure in here to avoid ownership problems.
@@ -114,38 +172,93 @@ pub fn schedule_update(user: &str, repo: &str, sha: &str) {
         let _ : bool = redis.expire(lock_key.clone(), 900).unwrap();
 
         // No background thread yet, we are ready to roll: execute `update_for_github`
-        // and parse the result. If there is ClippyResult, match it to the appropriate
-        // status output, otherwise, report the error and set the status to "failed".
+        // and put the outcome into a `ClippyRecord` with the appropriate state,
+        // counts and color, otherwise, report the error and set the state to
+        // `Failed`. The diagnostics, the patch and the path of the crate within
+        // the repository are stored next to the result for the reports.
 
         logger("Started Processing");
+        let started_at = now_utc().rfc3339().to_string();
+        let start = precise_time_ns();
 
-        let text: String = match update_for_github(&user, &repo, &sha, logger) {
-            Ok(result) => {
+        let mut record = match update_for_github(&user, &repo, &sha, logger) {
+            Ok(Outcome { run, fixes, prefix, thresholds, durations }) => {
+                let ClippyRun { result, diagnostics, toolchain } = run;
+                // An internal compiler error cuts the run short, so there are
+                // no diagnostics to store – storing none would make the
+                // reports look like a clean run.
                 match result {
-                    ClippyResult::Success => String::from("success"),
-                    ClippyResult::WithWarnings(warnings) => format!("{0} warnings", warnings),
-                    ClippyResult::WithErrors(errors, _) => format!("{0} errors", errors)
+                    ClippyResult::Ice(_) => {}
+                    _ => set_diagnostics(&redis, &diagnostics_key, &diagnostics),
                 }
+                redis::pipe()
+                    .cmd("SET").arg(patch_key).arg(fixes).ignore()
+                    .cmd("SET").arg(prefix_key).arg(prefix).ignore()
+                    .execute(&redis);
+
+                let mut record = match result {
+                    ClippyResult::Success => ClippyRecord::new(State::Success, &thresholds.color(0)),
+                    ClippyResult::WithWarnings(warnings) => {
+                        let mut record = ClippyRecord::new(State::Warnings, &thresholds.color(warnings));
+                        record.warnings = warnings;
+                        record
+                    }
+                    ClippyResult::WithErrors(errors, warnings) => {
+                        let mut record = ClippyRecord::new(State::LintErrors, "red");
+                        record.errors = errors;
+                        record.warnings = warnings;
+                        record
+                    }
+                    ClippyResult::BuildFailed(errors) => {
+                        let mut record = ClippyRecord::new(State::BuildFailed, "red");
+                        record.errors = errors;
+                        record
+                    }
+                    // an internal compiler error isn't the projects fault
+                    ClippyResult::Ice(message) => {
+                        let mut record = ClippyRecord::new(State::Ice, "lightgrey");
+                        record.message = Some(message);
+                        record
+                    }
+                };
+                // If the project has a baseline, the counts are of the new ones only
+                record.baselined = diagnostics.iter().filter(|d| d.baselined).count() as u32;
+                record.raw = diagnostics.iter().fold(0, |raw, d| raw + d.occurrences);
+                record.toolchain = toolchain;
+                record.durations = durations;
+                record
             }
             Err(error) => {
                 log_redis(&redis, &log_key, &format!("Failed: {}", error));
-                String::from("failed")
+                let mut record = ClippyRecord::new(State::Failed, "red");
+                record.message = Some(error);
+                record
             }
         };
 
-        // log the output from clippy and set the result into the redis cache.
-        // we are done with our background thread. Rust will take care of cleaning
-        // up for us here automatically.
+        record.clippy_version = clippy_version();
+        record.started_at = Some(started_at);
+        record.finished_at = Some(now_utc().rfc3339().to_string());
+        record.durations.total = millis_since(start);
+
+        // log the output from clippy and set the record into the redis cache.
+        // we are done with our background thread. Rust will take care of
+        // cleaning up for us here automatically.
         log_redis(&redis,
                   &log_key,
                   &format!("------------------------------------------\n Clippy's final \
                             verdict: {}",
-                           text));
-        redis::pipe()
-            .cmd("SET")
-            .arg(result_key)
-            .arg(text.clone())
-            .ignore()
-            .execute(&redis);
+                           record.text()));
+        let mut pipe = redis::pipe();
+        pipe.cmd("SET").arg(result_key.clone()).arg(json::encode(&record).unwrap()).ignore();
+        // the next run starts with a fresh log
+        if record.state == State::Failed {
+            pipe.cmd("EXPIRE").arg(result_key).arg(FAILED_RESULT_SECONDS).ignore()
+                .cmd("EXPIRE").arg(log_key.clone()).arg(FAILED_RESULT_SECONDS).ignore();
+        }
+        pipe.execute(&redis);
+
+        // and let the requests waiting for it know
+        waiting::notify();
     });
 }
@@ -4,14 +4,18 @@ extern crate redis;
 extern crate rustc_serialize;
 extern crate hyper;
 extern crate url;
+extern crate time;
 
 extern crate router;
 
+use std::env;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::vec::Vec;
-use rustc_serialize::json::Json;
+use rustc_serialize::json::{self, Json};
 
 use iron::modifiers::Redirect;
-use iron::headers::{CacheControl, CacheDirective};
+use iron::headers::{Accept, CacheControl, CacheDirective, ContentType, ETag, EntityTag, HttpDate, LastModified};
 use iron::prelude::*;
 use iron::status;
 use iron::Url as iUrl;
@@ -20,44 +24,62 @@ use hyper::client::Client;
 
 use router::Router;
 
-use redis::{Commands, Value};
+use redis::{Commands, PipelineCommands, RedisResult, Value};
 
-use helpers::{setup_redis, fetch, get_status_or,  local_redir, set_redis_cache};
+use helpers::{setup_redis, fetch, get_record_or,  local_redir, set_redis_cache, get_diagnostics,
+              query_param, svg_mime, event_stream_mime, shields_escape, conditional};
 use github::schedule_update as schedule_github_update;
+use diagnostics::{Diagnostic, compare, counts};
+use history;
+use lints::{lint_groups, breakdown};
+use record::{ClippyRecord, State};
+use reports;
+use badge::Badge;
+use raster;
+use variants::{self, BadgeContext};
+use stream::LogStream;
+use waiting;
 
-// The base URL for our badges. We aren't actually compiling them ourselves,
-// but are reusing the great shields.io service.
+// The base URL for the badges we don't render ourselves. For those,
+// we are reusing the great shields.io service.
 static BADGE_URL_BASE: &'static str = "https://img.shields.io/badge/";
 
+// How long badges may be cached: the result of a SHA never changes, while
+// the one of a branch only holds as long as we remember its head.
+static SHA_MAX_AGE: u32 = 24 * 60 * 60;
+static BRANCH_MAX_AGE: u32 = 5 * 60;
 
-// Github Finder
-// Expand a branch name into the hash, cache the redirect for 5min
-// `/github/:user/:repo/badge.svg => /github/:user/:repo/:sha/badge.svg`
-pub fn github_finder(req: &mut Request) -> IronResult<Response> {
 
-    // Learn the parameters given to the request
-    let router = req.extensions.get::<Router>().unwrap();
-    let redis: redis::Connection = setup_redis();
-    let hyper_client: Client = Client::new();
+// ## Split Method
+// Split the requested file name into the method and the extension,
+// e.g. `badge.svg` into `badge` and `svg`.
+fn split_method(filename: &str) -> (&str, &str) {
+    let parts: Vec<&str> = filename.rsplitn(2, '.').collect();
+    match parts.len() {
+        2 => (parts[1], parts[0]),
+        _ => (parts[0], ""),
+    }
+}
 
-    let user = router.find("user").unwrap();
-    let repo = router.find("repo").unwrap();
-    let branch = router.find("branch").unwrap_or("master");
-    let method = router.find("method").unwrap_or("badge.svg");
+// ## Find SHA
+// Expand a branch name into the hash of its current head, caching the result
+// for 5min. If that fails, the error response to send instead is returned.
+fn find_sha(redis: &redis::Connection,
+            hyper_client: &Client,
+            user: &str,
+            repo: &str,
+            branch: &str)
+            -> Result<String, Response> {
 
-    // And the cache key we use to keep the map from branch->SHA
+    // The cache key we use to keep the map from branch->SHA
     let redis_key = format!("cached-sha/github/{0}/{1}:{2}", user, repo, branch);
 
-    // Let's see if redis has this key. If it does, redirect the request
-    // directly
+    // Let's see if redis has this key. If it does, we are done already
     match redis.get(redis_key.to_owned()) {
         Ok(Value::Data(sha)) => {
-            local_redir(&format!("/github/sha/{0}/{1}/{2}/{3}",
-                                 user,
-                                 repo,
-                                 String::from_utf8(sha).unwrap(),
-                                 method),
-                        &req.url)
+            String::from_utf8(sha).map_err(|_| {
+                Response::with((status::InternalServerError, "Couldn't read the cached SHA"))
+            })
         }
         // otherwise, we need to look up the current SHA for the branch
         _ => {
@@ -67,160 +89,790 @@ pub fn github_finder(req: &mut Request) -> IronResult<Response> {
                                      branch);
             // Fetch the content API request for the Github URL,
             // Parse its JSON and try to find the `SHA`-key.
-            if let Some(body) = fetch(&hyper_client, &github_url) {
+            if let Some(body) = fetch(hyper_client, &github_url) {
                 if let Ok(json) = Json::from_str(&body) {
                     if let Some(&Json::String(ref sha)) = json.find_path(&["object", "sha"]) {
-                        // Once found, store the SHA in the cache and redirect
-                        // the request to
-                        set_redis_cache(&redis, &redis_key, &sha);
-                        local_redir(&format!("/github/sha/{0}/{1}/{2}/{3}",
-                                             user,
-                                             repo,
-                                             sha,
-                                             method),
-                                    &req.url)
+                        // Once found, store the SHA in the cache, add it to the
+                        // history of the branch and return it
+                        set_redis_cache(redis, &redis_key, &sha);
+                        history::record(redis, user, repo, branch, &sha);
+                        Ok(sha.clone())
                     } else {
                         // If we couldn't find the SHA, then there is a problem
                         // we need to inform the user about. Usually this means
-                        // they did a typo or the content moved – either way, we
-                        // fire a 404 – Not Found.
+                        // they did a typo or the content moved – either way, we
+                        // fire a 404 – Not Found.
                         warn!("{}: SHA not found in JSON: {}", &github_url, &json);
-                        Ok(Response::with((status::NotFound,
-                                           format!("Couldn't find on Github {}", &github_url))))
+                        Err(Response::with((status::NotFound,
+                                            format!("Couldn't find on Github {}", &github_url))))
                     }
                 } else {
                     warn!("{}: Couldn't parse Githubs JSON response: {}",
                           &github_url,
                           &body);
-                    Ok(Response::with((status::InternalServerError,
-                                       "Couldn't parse Githubs JSON response")))
+                    Err(Response::with((status::InternalServerError,
+                                        "Couldn't parse Githubs JSON response")))
                 }
             } else {
-                Ok(Response::with((status::NotFound,
-                                   format!("Couldn't find on Github {}", &github_url))))
+                Err(Response::with((status::NotFound,
+                                    format!("Couldn't find on Github {}", &github_url))))
             }
         }
     }
 }
 
+// ## Find Commit
+// Expand a short SHA – like `abc1234`, as commonly used in compare URLs – into
+// the full one we store everything under. Github resolves branch names just
+// as well, so only if the full SHA starts with what we asked for, it's a
+// commit and the answer never changes – which we then keep around for good.
+fn find_commit(redis: &redis::Connection,
+               hyper_client: &Client,
+               user: &str,
+               repo: &str,
+               short: &str)
+               -> Option<String> {
+    let redis_key = format!("full-sha/github/{0}/{1}:{2}", user, repo, short);
+    if let Ok(Value::Data(sha)) = redis.get(redis_key.to_owned()) {
+        return String::from_utf8(sha).ok();
+    }
+
+    let github_url = format!("https://api.github.com/repos/{0}/{1}/commits/{2}", user, repo, short);
+    let sha = fetch(hyper_client, &github_url)
+                  .and_then(|body| Json::from_str(&body).ok())
+                  .and_then(|json| json.find("sha").and_then(|sha| sha.as_string()).map(|sha| sha.to_owned()));
+    match sha {
+        Some(sha) => {
+            if sha.starts_with(&short.to_lowercase()) {
+                redis::pipe()
+                    .cmd("SET").arg(redis_key).arg(sha.clone()).ignore()
+                    .execute(redis);
+                Some(sha)
+            } else {
+                None
+            }
+        }
+        None => None,
+    }
+}
+
+// ## Direct Badges
+// Whether branch badges are served right away rather than redirected to
+// their SHA. Set `CLIPPY_DIRECT_BADGES=1` to make it the default, a single
+// request may choose with `?direct=1` or `?direct=0`.
+fn direct_badges(url: &iUrl) -> bool {
+    let setting = query_param(url, "direct").or(env::var("CLIPPY_DIRECT_BADGES").ok());
+    match setting.as_ref().map(|setting| setting.as_str()) {
+        Some("1") | Some("true") | Some("yes") => true,
+        _ => false,
+    }
+}
+
+// Github Finder
+// Expand a branch name into the hash, cache the redirect for 5min
+// `/github/:user/:repo/badge.svg => /github/:user/:repo/:sha/badge.svg`
+// Image proxies don't always follow redirects well, so in direct mode badges
+// are rendered right here instead – cached only as long as the SHA we found.
+pub fn github_finder(req: &mut Request) -> IronResult<Response> {
+
+    // Learn the parameters given to the request
+    let router = req.extensions.get::<Router>().unwrap();
+    let redis: redis::Connection = setup_redis();
+    let hyper_client: Client = Client::new();
+
+    let user = router.find("user").unwrap();
+    let repo = router.find("repo").unwrap();
+    let branch = router.find("branch").unwrap_or("master");
+    // category badges have their own route, but are forwarded just the same
+    let method = match router.find("category") {
+        Some(category) => format!("badge/{}", category),
+        None => router.find("method").unwrap_or("badge.svg").to_owned(),
+    };
+    let is_badge = match split_method(&method) {
+        ("shields", "json") => true,
+        (name, _) => router.find("category").is_some() || variants::find(name).is_some(),
+    };
+
+    // Look up the SHA and redirect the request to it. While it's linted,
+    // the badge shows the result of the commit before – which only the
+    // branch knows, so we answer right here.
+    match find_sha(&redis, &hyper_client, user, repo, branch) {
+        Ok(sha) => {
+            let base_key = format!("github/{0}/{1}:{2}", user, repo, sha);
+            let linting = !get_record_or(&redis, &base_key, || {}).is_done();
+            if is_badge && (linting || direct_badges(&req.url)) {
+                let response = result_response(&redis,
+                                               req,
+                                               user,
+                                               repo,
+                                               &sha,
+                                               Some(branch),
+                                               router.find("method"),
+                                               router.find("category"),
+                                               BRANCH_MAX_AGE);
+                return Ok(conditional(req, response));
+            }
+            local_redir(&format!("/github/sha/{0}/{1}/{2}/{3}",
+                                 user,
+                                 repo,
+                                 sha,
+                                 method),
+                        &req.url)
+        }
+        Err(response) => Ok(response),
+    }
+}
+
+// ## Report Response
+// All reports are generated from the stored diagnostics. If those aren't
+// there yet, we are either still linting or the run failed – either way
+// there is nothing to render yet, so let the requester know why.
+fn report_response<F>(diagnostics: Option<Vec<Diagnostic>>, record: &ClippyRecord, render: F) -> Response
+    where F: Fn(&[Diagnostic]) -> String
+{
+    match diagnostics {
+        Some(diagnostics) => Response::with((status::Ok, render(&diagnostics))),
+        None => {
+            match record.state {
+                State::Linting => Response::with((status::Accepted, "linting")),
+                _ => Response::with((status::NotFound, format!("No diagnostics found: {}", record.text()))),
+            }
+        }
+    }
+}
+
+// ## PNG Badge
+// Rasterizing takes a while, so we keep the images in redis for a week. As
+// the same label, text, color and query parameters always make the same
+// image, that's what we key them by – which makes them cached per result.
+// If we can't rasterize, we return `None`, to fall back to shields.io.
+fn png_badge(redis: &redis::Connection, badge: &Badge, text: &str, color: &str, url: &iUrl) -> Option<Vec<u8>> {
+    let scale = match query_param(url, "scale").as_ref().map(|scale| scale.as_str()) {
+        Some("2") => 2,
+        _ => 1,
+    };
+    let mut hasher = DefaultHasher::new();
+    (&badge.label, text, color, &url.query, scale).hash(&mut hasher);
+    let key = format!("badge-png/{:x}", hasher.finish());
+
+    let cached: RedisResult<Option<Value>> = redis.get(key.clone());
+    if let Ok(Some(Value::Data(png))) = cached {
+        return Some(png);
+    }
+    match raster::png(badge, scale) {
+        Ok(png) => {
+            redis::pipe()
+                .cmd("SET").arg(key.clone()).arg(&png[..]).ignore()
+                .cmd("EXPIRE").arg(key.clone()).arg(7 * 24 * 60 * 60).ignore()
+                .execute(redis);
+            Some(png)
+        }
+        Err(error) => {
+            warn!("Rendering the PNG badge failed: {}", error);
+            None
+        }
+    }
+}
+
+// ## Badge Response
+// Render the badge for the given label, text and color. SVG and PNG (add
+// `?scale=2` for twice the resolution) badges we draw ourselves, customized
+// by the query parameters. They may be cached for `max_age` seconds – unless
+// the badge is `temporary`, like while linting, then not at all. For any other
+// format we still forward to shields.io, passing on the query parameters of
+// the incoming request, redirecting permanently only for results that never
+// change, so that the actual result will be asked for later.
+fn badge_response(redis: &redis::Connection,
+                  label: &str,
+                  text: &str,
+                  color: &str,
+                  ext: &str,
+                  temporary: bool,
+                  max_age: u32,
+                  url: &iUrl)
+                  -> Response {
+    let mut badge = Badge::new(label, text, color);
+    let rendered = match (badge.customize(url), ext) {
+        (Err(error), "svg") | (Err(error), "png") => return Response::with((status::BadRequest, error)),
+        (Ok(_), "svg") => Some((svg_mime(), badge.render().into_bytes())),
+        (Ok(_), "png") => png_badge(redis, &badge, text, color, url).map(|png| (mime!(Image/Png), png)),
+        _ => None,
+    };
+    if let Some((mime, body)) = rendered {
+        let mut response = Response::with((status::Ok, mime, body));
+        response.headers.set(CacheControl(if temporary {
+            vec![CacheDirective::NoCache]
+        } else {
+            vec![CacheDirective::Public, CacheDirective::MaxAge(max_age)]
+        }));
+        return response;
+    }
+
+    let badge = format!("{}{}-{}-{}.{}",
+                        BADGE_URL_BASE,
+                        shields_escape(label),
+                        shields_escape(text),
+                        // shields.io takes hex colors without the `#`
+                        shields_escape(color.trim_left_matches('#')),
+                        shields_escape(ext));
+    let target_badge = match url.query {
+        Some(ref query) => format!("{}?{}", badge, query),
+        None => badge,
+    };
+    let permanent = !temporary && max_age >= SHA_MAX_AGE;
+    // the query parameters are passed on as they are, which might not make a URL
+    match iUrl::parse(&target_badge) {
+        Ok(target) => {
+            Response::with((if permanent { status::MovedPermanently } else { status::Found }, Redirect(target)))
+        }
+        Err(error) => Response::with((status::BadRequest, format!("Can't build the badge URL: {}", error))),
+    }
+}
+
+// ## Stale Record
+// The result of the latest commit before this one on the branch, that we
+// have a result for – skipping commits pushed over before their run finished.
+// Along with it, the base key to find everything else stored for it.
+fn stale_record(redis: &redis::Connection,
+                user: &str,
+                repo: &str,
+                branch: &str,
+                sha: &str)
+                -> Option<(String, ClippyRecord)> {
+    history::previous(redis, user, repo, branch, sha)
+        .into_iter()
+        .take(5)
+        .map(|sha| {
+            let base_key = format!("github/{0}/{1}:{2}", user, repo, sha);
+            let record = get_record_or(redis, &base_key, || {});
+            (base_key, record)
+        })
+        .find(|&(_, ref record)| record.is_done())
+}
+
 // ## Github Handler
 // Handle the request for a status report of a user-repo-sha combination.
 // Usually the request ends up here after having been redirected via the
 // `github_finder`-handler.
-// In this request is where the actual sausage is done.
 pub fn github_handler(req: &mut Request) -> IronResult<Response> {
+    let router = req.extensions.get::<Router>().unwrap();
+    let redis: redis::Connection = setup_redis();
+
+    let response = result_response(&redis,
+                                   req,
+                                   router.find("user").unwrap(),
+                                   router.find("repo").unwrap(),
+                                   router.find("sha").unwrap(),
+                                   None,
+                                   router.find("method"),
+                                   router.find("category"),
+                                   SHA_MAX_AGE);
+    Ok(conditional(req, response))
+}
+
+// ## Accepts JSON
+// Whether the requester asked for JSON in the `Accept`-header
+fn accepts_json(req: &Request) -> bool {
+    match req.headers.get::<Accept>() {
+        Some(&Accept(ref items)) => items.iter().any(|item| item.item == mime!(Application/Json)),
+        None => false,
+    }
+}
+
+// ## Result Response
+// Answer the request for the result of the given commit with the `method`
+// asked for, badges being cacheable for `max_age` seconds. Asked for as the
+// head of a `branch`, the badges may show the result of a commit before.
+// In this function is where the actual sausage is done.
+fn result_response(redis: &redis::Connection,
+                   req: &Request,
+                   user: &str,
+                   repo: &str,
+                   sha: &str,
+                   branch: Option<&str>,
+                   method: Option<&str>,
+                   category: Option<&str>,
+                   max_age: u32)
+                   -> Response {
 
     // First extract all the request information
+    let url = &req.url;
+    let (method, ext) = split_method(category.or(method).unwrap_or("badge.svg"));
+    // The status is told as JSON at `status.json`, or if that's what is accepted
+    let (method, ext) = match (method, ext) {
+        ("status", "") if accepts_json(req) => ("status", "json"),
+        _ => (method, ext),
+    };
+    // Category badges are routed as `badge/:category`, e.g. `badge/perf.svg`
+    let (method, category) = match category {
+        Some(_) => ("categorybadge", Some(method)),
+        None => (method, None),
+    };
+    // and a badge for a single lint via `badge.svg?lint=unwrap_used`
+    let lint = query_param(url, "lint");
+    let method = match (method, &lint) {
+        ("badge", &Some(_)) => "lintbadge",
+        _ => method,
+    };
+
+    // Use `get_record_or` to look up the cached result
+    // or trigger a `schedule_github_update` if that isn't found yet
+    let base_key = format!("github/{0}/{1}:{2}", user, repo, sha);
+    let record = get_record_or(redis, &base_key, || schedule_github_update(&user, &repo, &sha));
+    // Scripts may wait for the result with `status?wait=60`, for up to that
+    // many seconds, rather than asking again and again while we are linting
+    let wait = match method {
+        "status" => query_param(url, "wait").and_then(|seconds| seconds.parse::<u64>().ok()),
+        _ => None,
+    };
+    let record = match wait {
+        Some(seconds) if !record.is_done() => {
+            let finished = waiting::wait_for(seconds, || {
+                let record = get_record_or(redis, &base_key, || {});
+                if record.is_done() {
+                    Some(record)
+                } else {
+                    None
+                }
+            });
+            finished.unwrap_or(record)
+        }
+        _ => record,
+    };
+    let text = record.text();
+    let diagnostics_key = format!("diagnostics/{}", base_key);
+    // the reports point into the repository, the crate may be in a folder of it
+    let prefix: String = redis.get(format!("prefix/{}", base_key)).unwrap_or(String::new());
+
+    // While the head of a branch is being linted, its badges show the result of
+    // the commit the branch pointed to before, so they don't flicker on every
+    // push – `?stale=mark` marks them as updating, `?stale=no` shows linting
+    // instead. Badges of a SHA always show the result of just that commit.
+    let stale_mode = query_param(url, "stale");
+    let stale = match (record.state, stale_mode.as_ref().map(|mode| mode.as_str()), branch) {
+        (State::Linting, Some("no"), _) => None,
+        (State::Linting, _, Some(branch)) => stale_record(redis, user, repo, branch, sha),
+        _ => None,
+    };
+    let (badge_key, badge_record) = match stale {
+        Some((ref key, ref record)) => (key.clone(), record),
+        None => (base_key.clone(), &record),
+    };
+    let mark = |text: String| {
+        match (&stale, stale_mode.as_ref().map(|mode| mode.as_str())) {
+            (&Some(_), Some("mark")) => format!("{} (updating)", text),
+            _ => text,
+        }
+    };
+    // A failed run is forgotten after an hour and linted again (see
+    // `github::schedule_update`), so its badge is not for keeps either
+    let temporary = match badge_record.state {
+        State::Linting | State::Failed => true,
+        _ => stale.is_some(),
+    };
+
+    // Then render the response. All the badges are drawn by `badge_response`
+    // from the label, text and color the variant registered for the method
+    // gives us.
+    let mut response = if let Some(renderer) = variants::find(method) {
+        let context = BadgeContext {
+            redis: redis,
+            base_key: &badge_key,
+            record: badge_record,
+            argument: category.or(lint.as_ref().map(|lint| lint.as_str())),
+        };
+        match renderer.render(&context) {
+            Ok((label, text, color)) => {
+                badge_response(redis, &label, &mark(text), &color, ext, temporary, max_age, url)
+            }
+            Err(message) => Response::with((status::BadRequest, message)),
+        }
+    } else {
+        match method {
+            // Scripts may ask for everything we know about the result at once,
+            // with links to read more
+            "status" if ext == "json" => {
+                let base_url = format!("/github/sha/{0}/{1}/{2}", user, repo, sha);
+                Response::with((status::Ok,
+                                mime!(Application/Json),
+                                reports::status(&record,
+                                                sha,
+                                                &format!("{}/log", base_url),
+                                                &format!("{}/report.sarif", base_url))
+                                    .to_string()))
+            },
+            // If this is a simple request for status, just return the result,
+            // for internal compiler errors including the panic message
+            "status" => {
+                match (record.state, &record.message) {
+                    (State::Ice, &Some(ref message)) => Response::with((status::Ok, format!("ice: {}", message))),
+                    _ => Response::with((status::Ok, text.to_owned())),
+                }
+            },
+            // The badge as data, for renderers compatible with shields.io. Until we
+            // have the result, ask them to come back as soon as they may – which
+            // is after five minutes for shields.io.
+            "shields" if ext == "json" => {
+                let cache_seconds = if temporary { 5 * 60 } else { max_age };
+                let mut response = Response::with((status::Ok,
+                                                   mime!(Application/Json),
+                                                   reports::shields("clippy",
+                                                                    &mark(badge_record.text()),
+                                                                    &badge_record.color,
+                                                                    cache_seconds as u64,
+                                                                    badge_record.state == State::Failed)
+                                                       .to_string()));
+                response.headers.set(CacheControl(vec![CacheDirective::MaxAge(cache_seconds)]));
+                response
+            },
+            // If the request is asking for the logs, fetch those. This isn't particularly
+            // simple as the Redis library makes the unwrapping a little bit tricky and hard
+            // for rust to guess the proper types. So we have to specify the types and iterator
+            // rather explictly at times.
+            "log" => {
+                let log_key = format!("log/github/{0}/{1}:{2}", user, repo, sha);
+                match redis.lrange(log_key.to_owned(), 0, -1) {
+                    Ok(Some(Value::Bulk(logs))) => {
+                        let logs: Vec<String> = logs.iter()
+                                                    .map(|ref v| {
+                                                        match **v {
+                                                            Value::Data(ref val) => {
+                                            String::from_utf8(val.to_owned())
+                                                .unwrap()
+                                                .to_owned()
+                                        }
+                                                            _ => "".to_owned(),
+                                                        }
+                                                    })
+                                                    .collect();
+                        Response::with((status::Ok, logs.join("\n")))
+                    }
+                    // if there aren't any logs found, we might just started the
+                    // process. Let the request know.
+                    _ => {
+                        Response::with((status::Ok, "Started. Please refresh – or follow along at log/stream"))
+                    }
+                }
+            },
+            // The reports render the stored diagnostics into formats other tools
+            // can import, like SARIF for code-scanning.
+            "report" if ext == "sarif" => {
+                let mut response = report_response(get_diagnostics(redis, &diagnostics_key),
+                                                   &record,
+                                                   |diagnostics| reports::sarif(diagnostics, &prefix).to_string());
+                response.headers.set(ContentType(mime!(Application/Json)));
+                response
+            },
+            // The breakdown counts the lints found by name and by category
+            "breakdown" if ext == "json" => {
+                let mut response = report_response(get_diagnostics(redis, &diagnostics_key),
+                                                   &record,
+                                                   |diagnostics| {
+                    reports::breakdown(&breakdown(diagnostics, lint_groups())).to_string()
+                });
+                response.headers.set(ContentType(mime!(Application/Json)));
+                response
+            },
+            // The baseline of the current diagnostics, to be committed
+            // as `clippy-baseline.json` next to the `Cargo.toml`
+            "baseline" if ext == "json" => {
+                let mut response = report_response(get_diagnostics(redis, &diagnostics_key),
+                                                   &record,
+                                                   |diagnostics| reports::baseline(diagnostics).pretty().to_string());
+                response.headers.set(ContentType(mime!(Application/Json)));
+                response
+            },
+            // and Checkstyle or JUnit XML for the CI dashboards
+            "report.checkstyle" | "report.junit" if ext == "xml" => {
+                let render: fn(&[Diagnostic], &str) -> String = if method == "report.checkstyle" {
+                    reports::checkstyle
+                } else {
+                    reports::junit
+                };
+                let mut response = report_response(get_diagnostics(redis, &diagnostics_key),
+                                                   &record,
+                                                   |diagnostics| render(diagnostics, &prefix));
+                response.headers.set(ContentType(mime!(Application/Xml)));
+                response
+            },
+            // The machine applicable fixes clippy suggested, as a patch ready to
+            // be applied with `curl .../fix.patch | git apply`.
+            "fix" if ext == "patch" => {
+                let patch: Option<String> = redis.get(format!("patch/github/{0}/{1}:{2}", user, repo, sha))
+                                                 .ok();
+                match patch {
+                    // `git apply` refuses an empty patch, so that's no fixes either
+                    Some(ref patch) if patch.is_empty() => Response::with((status::NotFound, "No fixes")),
+                    Some(patch) => Response::with((status::Ok, mime!(Text/Plain; Charset=Utf8), patch)),
+                    None => {
+                        match record.state {
+                            State::Linting => Response::with((status::Accepted, "linting")),
+                            _ => Response::with((status::NotFound, format!("No fixes found: {}", text))),
+                        }
+                    }
+                }
+            },
+            // Nothing else is supported – but in rust, we have to return all things
+            // of the same type. So let's return a `BadRequst` :) .
+            _ => Response::with((status::BadRequest, format!("{} Not Implemented.", method))),
+        }
+    };
+
+    // The status depends on what the requester accepts
+    if method == "status" {
+        response.headers.set_raw("Vary", vec![b"Accept".to_vec()]);
+    }
+
+    // Badges bring their own caching, everything else is about to change
+    if !response.headers.has::<CacheControl>() {
+        response.headers.set(CacheControl(vec![CacheDirective::NoCache]));
+    }
+
+    // Once the run is done, nothing changes until the commit is linted again –
+    // the log, reports and badges all come from its record. So the commit, when
+    // its run finished and what was asked for is what we tag the responses
+    // with, for the requester to ask whether it still has the latest version.
+    if record.is_done() {
+        let tag = format!("{}-{}-{}.{}?{}",
+                          sha,
+                          record.finished_at.as_ref().map(|at| at.as_str()).unwrap_or(""),
+                          category.map(|category| format!("{}/{}", method, category)).unwrap_or(method.to_owned()),
+                          ext,
+                          url.query.as_ref().map(|query| query.as_str()).unwrap_or(""));
+        response.headers.set(ETag(EntityTag::new(false, entity_tag_escape(&tag))));
+        let finished = record.finished_at.as_ref().and_then(|at| time::strptime(at, "%Y-%m-%dT%H:%M:%SZ").ok());
+        if let Some(finished) = finished {
+            response.headers.set(LastModified(HttpDate(finished)));
+        }
+    }
+    response
+}
+
+// Entity tags are quoted and can't hold quotes, spaces or control characters
+// – those are percent-encoded, like they would be in the URL.
+fn entity_tag_escape(tag: &str) -> String {
+    tag.bytes()
+       .map(|byte| {
+           match byte {
+               b'!' | b'#'...b'~' => (byte as char).to_string(),
+               _ => format!("%{:02X}", byte),
+           }
+       })
+       .collect()
+}
+
+// ## Github Log Stream
+// Stream the log of a run live, for the SHA or the head of the branch given,
+// starting it if need be. Browsers reconnecting tell us the id of the last
+// line they got, so we continue from the one after.
+pub fn github_log_stream(req: &mut Request) -> IronResult<Response> {
     let router = req.extensions.get::<Router>().unwrap();
     let redis: redis::Connection = setup_redis();
 
     let user = router.find("user").unwrap();
     let repo = router.find("repo").unwrap();
-    let sha = router.find("sha").unwrap();
-    let filename: Vec<&str> = router.find("method")
-                                    .unwrap_or("badge.svg")
-                                    .rsplitn(2, '.')
-                                    .collect();
-    let (method, ext) = match filename.len() {
-        2 => (filename[1], filename[0]),
-        _ => (filename[0], ""),
+    let sha = match router.find("sha") {
+        Some(sha) => sha.to_owned(),
+        None => {
+            let branch = router.find("branch").unwrap_or("master");
+            match find_sha(&redis, &Client::new(), user, repo, branch) {
+                Ok(sha) => sha,
+                Err(response) => return Ok(response),
+            }
+        }
     };
 
-    // Use `get_status_or` to look up and map the cached result
-    // or trigger a `schedule_github_update` if that isn't found yet
-    let result_key = format!("result/github/{0}/{1}:{2}", user, repo, sha);
-    let (text, color): (String, String) = get_status_or(
-        redis.get(result_key.to_owned()),
-        || schedule_github_update(&user, &repo, &sha));
+    let base_key = format!("github/{0}/{1}:{2}", user, repo, sha);
+    get_record_or(&redis, &base_key, || schedule_github_update(&user, &repo, &sha));
 
-    // Then render the response
-    let mut response = match method {
-        // If this is a simple request for status, just return the result
-        "status" => Response::with((status::Ok, text.to_owned())),
-        // for the badge, put text, color, base URL and query-parameters from the
-        // incoming requests together to the URL we need to forward it to
-        "badge" => {
-            let target_badge = match req.url.clone().query {
-                Some(query) => format!("{}clippy-{}-{}.{}?{}", BADGE_URL_BASE, text, color, ext, query),
-                _ => format!("{}clippy-{}-{}.{}", BADGE_URL_BASE, text, color, ext),
-            };
-            // while linting, use only temporary redirects, so that the actual
-            // result will be asked for later
-            Response::with((match text.as_str() {
-                    "linting" => status::Found,
-                    _ => status::MovedPermanently
-                }, Redirect(iUrl::parse(&target_badge).unwrap())))
-        },
-        // emojibadge and fullemojibadge do the same as the request for `badge`,
-        // except that they replace the status with appropriate emoji
-        "emojibadge" => {
-            let emoji = match text.as_str() {
-                "linting" => "👷".to_string(),
-                "failed" => "😱".to_string(),
-                "success" => "👌".to_string(),
-                _ => text.replace("errors", "🤕").replace("warnings", "😟")
-            };
+    let cursor = req.headers
+                    .get_raw("Last-Event-ID")
+                    .and_then(|values| values.first())
+                    .and_then(|value| String::from_utf8(value.clone()).ok())
+                    .and_then(|id| id.trim().parse::<isize>().ok())
+                    .map(|id| id + 1)
+                    .unwrap_or(0);
 
-            let target_badge = match req.url.clone().query {
-                Some(query) => format!("{}clippy-{}-{}.{}?{}", BADGE_URL_BASE, emoji, color, ext, query),
-                _ => format!("{}clippy-{}-{}.{}", BADGE_URL_BASE, emoji, color, ext),
-            };
-            Response::with((match color.as_str() {
-                    "blue" => status::Found,
-                    _ => status::MovedPermanently
-                }, Redirect(iUrl::parse(&target_badge).unwrap())))
-        },
-        "fullemojibadge" => {
-            let emoji = match text.as_str() {
-                "linting" => "👷".to_string(),
-                "failed" => "😱".to_string(),
-                "success" => "👌".to_string(),
-                _ => text.replace("errors", "🤕").replace("warnings", "😟")
-            };
+    let mut response = Response::with((status::Ok, event_stream_mime()));
+    response.headers.set(CacheControl(vec![CacheDirective::NoCache]));
+    response.body = Some(Box::new(LogStream::new(&base_key, cursor)));
+    Ok(response)
+}
 
-            let target_badge = match req.url.clone().query {
-                Some(query) => format!("{}📎-{}-{}.{}?{}", BADGE_URL_BASE, emoji, color, ext, query),
-                _ => format!("{}📎-{}-{}.{}", BADGE_URL_BASE, emoji, color, ext),
-            };
-            Response::with((match color.as_str() {
-                    "blue" => status::Found,
-                    _ => status::MovedPermanently
-                }, Redirect(iUrl::parse(&target_badge).unwrap())))
-        },
-        // If the request is asking for the logs, fetch those. This isn't particularly
-        // simple as the Redis library makes the unwrapping a little bit tricky and hard
-        // for rust to guess the proper types. So we have to specify the types and iterator
-        // rather explictly at times.
-        "log" => {
-            let log_key = format!("log/github/{0}/{1}:{2}", user, repo, sha);
-            match redis.lrange(log_key.to_owned(), 0, -1) {
-                Ok(Some(Value::Bulk(logs))) => {
-                    let logs: Vec<String> = logs.iter()
-                                                .map(|ref v| {
-                                                    match **v {
-                                                        Value::Data(ref val) => {
-                                        String::from_utf8(val.to_owned())
-                                            .unwrap()
-                                            .to_owned()
-                                    }
-                                                        _ => "".to_owned(),
-                                                    }
-                                                })
-                                                .collect();
-                    Response::with((status::Ok, logs.join("\n")))
+// ## Github Compare
+// Compare the results of two commits and report which diagnostics `head`
+// introduced or fixed compared to `base`:
+// `/github/:user/:repo/compare/:base...:head/:method`
+// Both may be given as SHA or branch name. Just like for single commits,
+// clippy is run for any of the two we don't have a result for yet.
+pub fn github_compare(req: &mut Request) -> IronResult<Response> {
+
+    // First extract all the request information
+    let router = req.extensions.get::<Router>().unwrap();
+    let redis: redis::Connection = setup_redis();
+    let hyper_client: Client = Client::new();
+
+    let user = router.find("user").unwrap();
+    let repo = router.find("repo").unwrap();
+    let range: Vec<&str> = router.find("range").unwrap().splitn(2, "...").collect();
+    let (method, ext) = split_method(router.find("method").unwrap_or("badge.svg"));
+
+    if range.len() != 2 {
+        return Ok(Response::with((status::BadRequest, "Expected a range like `base...head`")));
+    }
+
+    // Resolve short SHAs and branch names into SHAs, then look up the record and
+    // diagnostics of each of them. If we don't have the diagnostics, fall back to
+    // its record. Whatever looks like a SHA but isn't one, may still be a branch.
+    let mut sides: Vec<(String, Result<Vec<Diagnostic>, ClippyRecord>)> = vec![];
+    let mut from_branch = false;
+    for reference in range {
+        let looks_like_sha = reference.len() >= 7 && reference.len() <= 40 &&
+                             reference.chars().all(|c| c.is_digit(16));
+        let commit = match (looks_like_sha, reference.len()) {
+            (true, 40) => Some(reference.to_lowercase()),
+            (true, _) => find_commit(&redis, &hyper_client, user, repo, reference),
+            _ => None,
+        };
+        from_branch = from_branch || commit.is_none();
+        let sha = match commit {
+            Some(sha) => sha,
+            None => {
+                match find_sha(&redis, &hyper_client, user, repo, reference) {
+                    Ok(sha) => sha,
+                    Err(response) => return Ok(response),
+                }
+            }
+        };
+        let base_key = format!("github/{0}/{1}:{2}", user, repo, sha);
+        let record = get_record_or(&redis, &base_key, || schedule_github_update(&user, &repo, &sha));
+        let diagnostics = get_diagnostics(&redis, &format!("diagnostics/{}", base_key)).ok_or(record);
+        sides.push((sha, diagnostics));
+    }
+
+    let comparison = match (&sides[0].1, &sides[1].1) {
+        (&Ok(ref base), &Ok(ref head)) => Ok(compare(base, head)),
+        // one of them is still linting or the run failed
+        (&Err(ref record), _) | (_, &Err(ref record)) => Err(record.clone()),
+    };
+
+    // Summarize the comparison as "+introduced / -fixed". Not introducing any new
+    // warnings is what we are looking for, so that's green.
+    let (text, color) = match comparison {
+        Ok(ref comparison) => {
+            (format!("+{} / -{}", comparison.introduced.len(), comparison.fixed.len()),
+             String::from(if comparison.introduced.is_empty() { "brightgreen" } else { "red" }))
+        }
+        Err(ref record) if record.state == State::Linting => (record.text(), String::from("blue")),
+        Err(ref record) => (record.text(), String::from("red")),
+    };
+    // Comparing a branch, the badge changes as soon as the branch moves on
+    let unfinished = match comparison {
+        Err(ref record) => record.state == State::Linting || record.state == State::Failed,
+        _ => false,
+    };
+    let temporary = unfinished || from_branch;
+
+    let mut response = match method {
+        "status" => Response::with((status::Ok, text.to_owned())),
+        "badge" => badge_response(&redis, "clippy", &text, &color, ext, temporary, SHA_MAX_AGE, &req.url),
+        "report" if ext == "json" => {
+            match comparison {
+                Ok(ref comparison) => {
+                    Response::with((status::Ok,
+                                    mime!(Application/Json),
+                                    reports::comparison(&sides[0].0, &sides[1].0, comparison)
+                                        .to_string()))
+                }
+                Err(ref record) if record.state == State::Linting => {
+                    Response::with((status::Accepted, "linting"))
                 }
-                // if there aren't any logs found, we might just started the
-                // process. Let the request know.
-                _ => {
-                    Response::with((status::Ok, "Started. Please refresh"))
+                Err(ref record) => {
+                    Response::with((status::NotFound, format!("No diagnostics found: {}", record.text())))
                 }
             }
         },
-        // Nothing else is supported – but in rust, we have to return all things
-        // of the same type. So let's return a `BadRequst` :) .
         _ => Response::with((status::BadRequest, format!("{} Not Implemented.", method))),
     };
 
+    // Branches move on, so whatever we answered may change any time –
+    // even the badge, despite the commits results being final.
+    response.headers.set(CacheControl(vec![CacheDirective::NoCache]));
+    Ok(response)
+}
+
+// ## Branch History
+// Look up the recorded heads of the branch with their status and – if the
+// run succeeded – their warning and error counts. Heads we haven't got a
+// result for aren't scheduled for linting here: this is about the past.
+// Results stored before we had records don't keep all counts, so for
+// those we count the diagnostics.
+fn branch_history(redis: &redis::Connection,
+                  user: &str,
+                  repo: &str,
+                  branch: &str)
+                  -> Vec<(history::Entry, String, Option<(u32, u32)>)> {
+    history::load(redis, user, repo, branch)
+        .into_iter()
+        .map(|entry| {
+            let base_key = format!("github/{0}/{1}:{2}", user, repo, entry.sha);
+            let record = get_record_or(redis, &base_key, || {});
+            let counts = match record.state {
+                State::Success | State::Warnings | State::LintErrors if record.version > 0 => {
+                    Some((record.warnings, record.errors))
+                }
+                _ => get_diagnostics(redis, &format!("diagnostics/{}", base_key)).map(|d| counts(&d)),
+            };
+            (entry, record.text(), counts)
+        })
+        .collect()
+}
+
+// ## Github History
+// The history of a branch as JSON: `/github/:user/:repo/:branch/history.json`
+pub fn github_history(req: &mut Request) -> IronResult<Response> {
+    let router = req.extensions.get::<Router>().unwrap();
+    let redis: redis::Connection = setup_redis();
+
+    let user = router.find("user").unwrap();
+    let repo = router.find("repo").unwrap();
+    let branch = router.find("branch").unwrap_or("master");
+
+    let entries = branch_history(&redis, user, repo, branch);
+    let mut response = Response::with((status::Ok,
+                                       mime!(Application/Json),
+                                       reports::history(&entries).to_string()));
+    response.headers.set(CacheControl(vec![CacheDirective::NoCache]));
+    Ok(response)
+}
+
+// ## Github Trend
+// A sparkline of the warnings and errors over the history of a branch:
+// `/github/:user/:repo/:branch/trend.svg`
+pub fn github_trend(req: &mut Request) -> IronResult<Response> {
+    let router = req.extensions.get::<Router>().unwrap();
+    let redis: redis::Connection = setup_redis();
+
+    let user = router.find("user").unwrap();
+    let repo = router.find("repo").unwrap();
+    let branch = router.find("branch").unwrap_or("master");
+
+    let counts: Vec<(u32, u32)> = branch_history(&redis, user, repo, branch)
+                                      .into_iter()
+                                      .filter_map(|(_, _, counts)| counts)
+                                      .collect();
+    let mut response = Response::with((status::Ok,
+                                       svg_mime(),
+                                       history::sparkline(&counts)));
     response.headers.set(CacheControl(vec![CacheDirective::NoCache]));
     Ok(response)
 }
+
+// ## Badge Variants
+// The variants of badges there are, for the badge builder on the front page:
+// `/badges.json`
+pub fn badge_variants(_: &mut Request) -> IronResult<Response> {
+    Ok(Response::with((status::Ok,
+                       mime!(Application/Json),
+                       reports::variants(variants::registry()).to_string())))
+}
@@ -12,6 +12,7 @@ extern crate router;
 extern crate mime;
 extern crate tempdir;
 extern crate zip;
+extern crate rustc_serialize;
 
 // and the specific imports we want
 use std::fs::File;
@@ -22,10 +23,12 @@ use std::env;
 use tempdir::TempDir;
 use time::now_utc;
 use zip::ZipArchive;
+use rustc_serialize::json;
 
 use redis::{Commands, RedisResult, PipelineCommands, Value};
 
-use iron::headers::{Location, CacheControl, CacheDirective};
+use iron::headers::{Location, CacheControl, CacheDirective, ETag, IfNoneMatch, IfModifiedSince, LastModified};
+use iron::method::Method;
 use iron::prelude::*;
 use iron::status;
 use iron::Url as iUrl;
@@ -34,6 +37,11 @@ use hyper::client::Client;
 use hyper::header::qitem;
 use hyper::header;
 
+use mime::{Mime, TopLevel, SubLevel};
+
+use diagnostics::Diagnostic;
+use record::ClippyRecord;
+
 // ## Download And Unzip
 // Given `source_url` and a target directory `tmp_dir` this helper function
 // tries to do download and unzip the given file there. Or returns a String with
@@ -177,43 +185,59 @@ pub fn set_redis_cache(redis: &redis::Connection, key: &str, value: &str) {
 }
 
 
-// ## Get Status Or
-// Reads the result of a Redis-Get-Query for the cached result and unpacks the value into
-// the badge relevant information of "text" and badge color we want to use, OR calls
-// the passed in `trigger` function if parsing failed. This is a handy function to look
-// up the redis key result, parse it or start the background process of executing a
-// clippy update
-pub fn get_status_or<F>(result: RedisResult<Option<Value>>, trigger: F) -> (String, String)
+// ## Set Diagnostics
+// Store the diagnostics of a clippy run as JSON under the given key. Like the
+// results themselves, these don't expire.
+pub fn set_diagnostics(redis: &redis::Connection, key: &str, diagnostics: &[Diagnostic]) {
+    redis::pipe()
+        .cmd("SET").arg(key.clone()).arg(json::encode(&diagnostics).unwrap()).ignore()
+        .execute(redis);
+}
+
+
+// ## Get Diagnostics
+// Read back the diagnostics stored by `set_diagnostics`. Returns `None` if
+// there aren't any (yet) or they couldn't be decoded.
+pub fn get_diagnostics(redis: &redis::Connection, key: &str) -> Option<Vec<Diagnostic>> {
+    let result: RedisResult<Option<Value>> = redis.get(key.to_owned());
+    match result {
+        Ok(Some(Value::Data(data))) => {
+            String::from_utf8(data).ok().and_then(|data| json::decode(&data).ok())
+        }
+        _ => None,
+    }
+}
+
+
+// ## Get Record Or
+// Reads the cached result stored for `base_key` – like `github/user/repo:sha` –
+// and decodes it into a `ClippyRecord`, OR calls the passed in `trigger` function
+// if there is none yet. This is a handy function to look up the redis key result,
+// parse it or start the background process of executing a clippy update.
+pub fn get_record_or<F>(redis: &redis::Connection, base_key: &str, trigger: F) -> ClippyRecord
     where F: Fn() {
+    let result: RedisResult<Option<Value>> = redis.get(format!("result/{}", base_key));
     match result {
         // Redis wraps the content in deep packs
         // With this comprehensive check of `Value` of type `Data` in `Some` in `Ok` we
-        // can be fairly certain this is the status we had stored before.
-        // Unfortunately that means, we get the raw Vector of `u8` here in `status` so
+        // can be fairly certain this is the result we had stored before.
+        // Unfortunately that means, we get the raw Vector of `u8` here in `data` so
         // we need to wrap that ourselfes again, before we can process
-        Ok(Some(Value::Data(status))) => {
-            let status = String::from_utf8(status).unwrap().to_owned();
-            (status.clone(), String::from(match status.as_str() {
-                // Map the status code to the appropriate color
-                "success" => "brightgreen",
-                "failed" => "red",
-                "linting" => "blue",
-                _ => {
-                    // Warnings and Errors contain the count, so we can't
-                    // directly map them.
-                    if status.ends_with("errors") {
-                        "red"
-                    } else { // warnings
-                        "yellow"
-                    }
+        Ok(Some(Value::Data(data))) => {
+            match String::from_utf8(data).ok().and_then(|data| ClippyRecord::decode(&data)) {
+                Some(record) => record,
+                // We can't make sense of it anymore, so lint again
+                None => {
+                    trigger();
+                    ClippyRecord::linting()
                 }
-            }))
+            }
         }
         _ => {
-            // The result given isn't a proper status as we expect it to
+            // The result given isn't a proper record as we expect it to
             // be stored. Trigger the update and return that we are "linting"
             trigger();
-            (String::from("linting"), String::from("blue"))
+            ClippyRecord::linting()
         }
     }
 }
@@ -236,6 +260,95 @@ pub fn local_redir(url: &str, source_url: &iUrl) -> IronResult<Response> {
     Ok(resp)
 }
 
+// ## Conditional
+// Answer conditional requests with `304 Not Modified`, if the response
+// carries the `ETag` or `Last-Modified` date of what the requester has
+// already – and `HEAD` requests with just the headers.
+pub fn conditional(req: &Request, mut response: Response) -> Response {
+    let not_modified = match req.headers.get::<IfNoneMatch>() {
+        Some(&IfNoneMatch::Any) => response.headers.has::<ETag>(),
+        Some(&IfNoneMatch::Items(ref tags)) => {
+            match response.headers.get::<ETag>() {
+                Some(&ETag(ref tag)) => tags.iter().any(|other| other.weak_eq(tag)),
+                None => false,
+            }
+        }
+        // the date is only looked at, if there is no tag to compare
+        None => {
+            match (req.headers.get::<IfModifiedSince>(), response.headers.get::<LastModified>()) {
+                (Some(&IfModifiedSince(ref since)), Some(&LastModified(ref modified))) => {
+                    modified.0.to_timespec() <= since.0.to_timespec()
+                }
+                _ => false,
+            }
+        }
+    };
+    if not_modified && response.status == Some(status::Ok) {
+        response.status = Some(status::NotModified);
+        response.body = None;
+    }
+    if req.method == Method::Head {
+        response.body = None;
+    }
+    response
+}
+
+// ## Query Param
+// Look up the value of the given query parameter in the url, if it is set.
+pub fn query_param(url: &iUrl, name: &str) -> Option<String> {
+    match url.query {
+        Some(ref query) => {
+            url::form_urlencoded::parse(query.as_bytes())
+                .into_iter()
+                .find(|&(ref key, _)| key == name)
+                .map(|(_, value)| value)
+        }
+        None => None,
+    }
+}
+
+// ## SVG Mime
+// The mime crate doesn't know about SVG, so we build its type ourselves
+pub fn svg_mime() -> Mime {
+    Mime(TopLevel::Image, SubLevel::Ext(String::from("svg+xml")), vec![])
+}
+
+// ## Event Stream Mime
+// The same goes for the `text/event-stream` of Server-Sent Events
+pub fn event_stream_mime() -> Mime {
+    Mime(TopLevel::Text, SubLevel::Ext(String::from("event-stream")), vec![])
+}
+
+// ## XML Escape
+// Escape text for use in XML attributes and content. Control characters
+// aren't allowed in XML at all – not even escaped – so we drop them.
+pub fn xml_escape(value: &str) -> String {
+    value.chars()
+         .filter(|c| !c.is_control() || *c == '\t' || *c == '\n' || *c == '\r')
+         .collect::<String>()
+         .replace("&", "&amp;")
+         .replace("<", "&lt;")
+         .replace(">", "&gt;")
+         .replace("\"", "&quot;")
+         .replace("'", "&apos;")
+}
+
+// ## Shields Escape
+// Escape text for a part of a shields.io badge URL, like
+// `/badge/label-message-color.svg`: dashes separate the parts and
+// underscores stand for spaces, so both are doubled to be taken literally.
+// Anything else but letters, digits, `.` and `~` is percent-encoded.
+pub fn shields_escape(part: &str) -> String {
+    let mut escaped = String::new();
+    for byte in part.replace("-", "--").replace("_", "__").bytes() {
+        match byte {
+            b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
 // ## fetch
 // Fetches a HTTP URL and returns the content as a String or `None` if anything
 // went wrong. Used as a handy function because Response reading is a little
@@ -0,0 +1,126 @@
+// Keep track of the commits a branch pointed to over time
+
+extern crate redis;
+extern crate rustc_serialize;
+extern crate time;
+
+use std::vec::Vec;
+
+use rustc_serialize::json;
+use redis::{Commands, RedisResult, PipelineCommands, Value};
+use time::now_utc;
+
+// We don't need the full history, the last 100 heads are plenty
+static MAX_ENTRIES: isize = 100;
+
+// ## Entry
+// A commit the branch pointed to and when we first saw it there
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Entry {
+    pub sha: String,
+    pub timestamp: String,
+}
+
+fn history_key(user: &str, repo: &str, branch: &str) -> String {
+    format!("history/github/{0}/{1}:{2}", user, repo, branch)
+}
+
+// ## Load
+// Read the history of the branch, oldest entry first
+pub fn load(redis: &redis::Connection, user: &str, repo: &str, branch: &str) -> Vec<Entry> {
+    let result: RedisResult<Option<Value>> = redis.lrange(history_key(user, repo, branch), 0, -1);
+    let mut entries: Vec<Entry> = match result {
+        Ok(Some(Value::Bulk(values))) => {
+            values.iter()
+                  .filter_map(|value| {
+                      match *value {
+                          Value::Data(ref data) => {
+                              String::from_utf8(data.clone()).ok().and_then(|d| json::decode(&d).ok())
+                          }
+                          _ => None,
+                      }
+                  })
+                  .collect()
+        }
+        _ => vec![],
+    };
+    // we push new heads to the front
+    entries.reverse();
+    entries
+}
+
+// ## Record
+// Remember the SHA as the current head of the branch – unless it
+// already is, then there is nothing new to record.
+pub fn record(redis: &redis::Connection, user: &str, repo: &str, branch: &str, sha: &str) {
+    let key = history_key(user, repo, branch);
+    let latest: RedisResult<Option<String>> = redis.lindex(key.clone(), 0);
+    let latest = match latest {
+        Ok(Some(latest)) => json::decode::<Entry>(&latest).ok(),
+        _ => None,
+    };
+    if let Some(ref latest) = latest {
+        if latest.sha == sha {
+            return;
+        }
+    }
+
+    let entry = Entry {
+        sha: sha.to_owned(),
+        timestamp: now_utc().rfc3339().to_string(),
+    };
+    redis::pipe()
+        .cmd("LPUSH").arg(key.clone()).arg(json::encode(&entry).unwrap()).ignore()
+        .cmd("LTRIM").arg(key.clone()).arg(0).arg(MAX_ENTRIES - 1).ignore()
+        .execute(redis);
+}
+
+// ## Previous
+// The commits the branch pointed to before this one, the latest first
+pub fn previous(redis: &redis::Connection, user: &str, repo: &str, branch: &str, sha: &str) -> Vec<String> {
+    let entries = load(redis, user, repo, branch);
+    match entries.iter().position(|entry| entry.sha == sha) {
+        Some(position) => entries[..position].iter().rev().map(|entry| entry.sha.clone()).collect(),
+        None => vec![],
+    }
+}
+
+// ## Sparkline
+// Render the counts as tiny line charts – warnings in yellow, errors in
+// red – to show the trend of a branch at a glance. Commits we don't have
+// counts for (yet) are left out.
+pub fn sparkline(counts: &[(u32, u32)]) -> String {
+    // a single point wouldn't show as a line, so draw it flat
+    let counts: Vec<(u32, u32)> = match counts.len() {
+        1 => vec![counts[0], counts[0]],
+        _ => counts.to_vec(),
+    };
+    let (width, height) = (100.0, 20.0);
+    let max = counts.iter().map(|&(warnings, errors)| if warnings > errors { warnings } else { errors })
+                    .max()
+                    .unwrap_or(0);
+    let scale_y = |value: u32| {
+        match max {
+            0 => height - 2.0,
+            _ => height - 2.0 - (value as f64 / max as f64) * (height - 4.0),
+        }
+    };
+    let step = if counts.len() > 1 { width / (counts.len() - 1) as f64 } else { 0.0 };
+    let points = |select: &Fn(&(u32, u32)) -> u32| {
+        counts.iter()
+              .enumerate()
+              .map(|(index, count)| format!("{:.1},{:.1}", index as f64 * step, scale_y(select(count))))
+              .collect::<Vec<String>>()
+              .join(" ")
+    };
+
+    format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"-2 0 {2} {1}\">\
+             <polyline fill=\"none\" stroke=\"#dfb317\" stroke-width=\"1.5\" points=\"{3}\"/>\
+             <polyline fill=\"none\" stroke=\"#e05d44\" stroke-width=\"1.5\" points=\"{4}\"/>\
+             </svg>",
+            width,
+            height,
+            width + 4.0,
+            points(&|&(warnings, _)| warnings),
+            points(&|&(_, errors)| errors))
+}
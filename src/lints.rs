@@ -0,0 +1,197 @@
+// Knowledge about clippy's lints, mainly which category each belongs to
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clippy::clippy_commit;
+use diagnostics::{Diagnostic, Kind};
+
+// The categories clippy sorts its lints into. The lints of rustc itself are
+// counted as `other`, lints we can't place at all as `unknown`.
+pub static CATEGORIES: [&'static str; 9] = ["correctness",
+                                            "suspicious",
+                                            "style",
+                                            "complexity",
+                                            "perf",
+                                            "pedantic",
+                                            "restriction",
+                                            "nursery",
+                                            "cargo"];
+
+lazy_static! {
+    static ref LINT_GROUPS: BTreeMap<String, String> = load_lint_groups();
+}
+
+// ## Clippy Source
+// Cargo keeps the checkout of a git dependency in a folder named after the
+// start of the commit, within one named after the repository.
+fn clippy_source() -> Option<PathBuf> {
+    let commit = match clippy_commit() {
+        Some(commit) => commit,
+        None => return None,
+    };
+    let cargo_home = match (env::var("CARGO_HOME"), env::var("HOME")) {
+        (Ok(cargo_home), _) => PathBuf::from(cargo_home),
+        (_, Ok(home)) => Path::new(&home).join(".cargo"),
+        _ => return None,
+    };
+    match fs::read_dir(cargo_home.join("git").join("checkouts")) {
+        Ok(checkouts) => {
+            checkouts.filter_map(|checkout| checkout.ok())
+                     .filter(|checkout| checkout.file_name().to_string_lossy().starts_with("rust-clippy-"))
+                     .map(|checkout| checkout.path().join(&commit[..7]))
+                     .find(|path| path.is_dir())
+        }
+        Err(_) => None,
+    }
+}
+
+// All the rust files below `dir`
+fn rust_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.is_dir() {
+                rust_files(&path, files);
+            } else if path.extension().map(|ext| ext == "rs").unwrap_or(false) {
+                files.push(path);
+            }
+        }
+    }
+}
+
+// ## Clippy Lints
+// Every lint of clippy is declared with its category, after its docs:
+// ```
+// declare_clippy_lint! {
+//     /// **What it does:** ...
+//     pub NEEDLESS_RETURN,
+//     style,
+//     "using a return statement like `return expr;` where an expression would suffice"
+// }
+// ```
+fn clippy_lints(source: &str, groups: &mut BTreeMap<String, String>) {
+    for declaration in source.split("declare_clippy_lint!").skip(1) {
+        let code = declaration.lines()
+                              .map(|line| line.trim())
+                              .filter(|line| !line.starts_with("//") && !line.starts_with("#["))
+                              .collect::<Vec<&str>>()
+                              .join(" ");
+        let fields: Vec<&str> = code.splitn(3, ',')
+                                    .map(|field| field.trim_matches(|c: char| c == '{' || c == '(' || c.is_whitespace()))
+                                    .collect();
+        if fields.len() < 3 || !CATEGORIES.contains(&fields[1]) {
+            continue;
+        }
+        if let Some(name) = fields[0].split_whitespace().last() {
+            groups.insert(name.to_lowercase(), fields[1].to_owned());
+        }
+    }
+}
+
+// ## Rustc Lints
+// The lints of rustc itself, as listed by `rustc -W help`:
+// ```
+// Lint checks provided by rustc:
+//
+//                     name  default  meaning
+//                     ----  -------  -------
+//             dead-code     warn     detect unused, unexported items
+// ```
+fn rustc_lints(groups: &mut BTreeMap<String, String>) {
+    let help = match Command::new("rustc").arg("-W").arg("help").output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
+        Err(_) => return,
+    };
+    let lints = help.lines()
+                    .skip_while(|line| !line.starts_with("Lint checks provided by rustc"))
+                    .skip(1)
+                    .take_while(|line| !line.starts_with("Lint groups provided"));
+    for lint in lints.filter_map(|line| line.split_whitespace().next()) {
+        if lint != "name" && !lint.starts_with("----") {
+            groups.insert(lint.replace("-", "_"), String::from("other"));
+        }
+    }
+}
+
+fn load_lint_groups() -> BTreeMap<String, String> {
+    let mut groups = BTreeMap::new();
+    rustc_lints(&mut groups);
+    if let Some(source) = clippy_source() {
+        let mut files = vec![];
+        rust_files(&source, &mut files);
+        for path in files {
+            let mut source = String::new();
+            if let Ok(_) = File::open(&path).and_then(|mut file| file.read_to_string(&mut source)) {
+                clippy_lints(&source, &mut groups);
+            }
+        }
+    }
+    groups
+}
+
+// ## Lint Groups
+// The categories of the lints of the clippy we ship with – and of rustc, as
+// `other`. Looked up once from the sources cargo checked out for our build,
+// as that's what the runs use. If those can't be found, no clippy lint can
+// be placed.
+pub fn lint_groups() -> &'static BTreeMap<String, String> {
+    &LINT_GROUPS
+}
+
+// ## Breakdown
+// The lints reported – warnings and denied ones alike – counted by lint name
+// and by category. Every category is always part of the result, so a
+// missing entry never has to be read as zero. As the counts are of unique
+// diagnostics, we also keep the raw count of how often they were emitted.
+// Without the groups of clippy, none of its lints could be placed, so
+// `groups_known` tells whether the counts per category mean anything.
+pub struct Breakdown {
+    pub lints: BTreeMap<String, u32>,
+    pub categories: BTreeMap<String, u32>,
+    pub total: u32,
+    pub raw: u32,
+    pub groups_known: bool,
+}
+
+impl Breakdown {
+    // ### Complete
+    // Whether every lint found could be placed in a category
+    pub fn complete(&self) -> bool {
+        self.groups_known && self.categories.get("unknown").cloned().unwrap_or(0) == 0
+    }
+}
+
+pub fn breakdown(diagnostics: &[Diagnostic], groups: &BTreeMap<String, String>) -> Breakdown {
+    let mut result = Breakdown {
+        lints: BTreeMap::new(),
+        categories: BTreeMap::new(),
+        total: 0,
+        raw: 0,
+        groups_known: groups.values().any(|group| CATEGORIES.contains(&group.as_str())),
+    };
+    for category in CATEGORIES.iter() {
+        result.categories.insert(category.to_string(), 0);
+    }
+    result.categories.insert(String::from("other"), 0);
+    result.categories.insert(String::from("unknown"), 0);
+
+    for diagnostic in diagnostics {
+        match (diagnostic.kind(), &diagnostic.lint) {
+            (Kind::Warning, &Some(ref lint)) |
+            (Kind::LintError, &Some(ref lint)) => {
+                *result.lints.entry(lint.clone()).or_insert(0) += 1;
+                let category = groups.get(lint).cloned().unwrap_or(String::from("unknown"));
+                *result.categories.entry(category).or_insert(0) += 1;
+                result.total += 1;
+                result.raw += diagnostic.occurrences;
+            }
+            _ => {}
+        }
+    }
+    result
+}
@@ -26,23 +26,46 @@ extern crate env_logger;
 #[macro_use]
 extern crate mime;
 
+// and the one to set up globals, like the badge variants and the requests waiting for results
+#[macro_use]
+extern crate lazy_static;
+
 extern crate tempdir;
 extern crate url;
 extern crate time;
 extern crate zip;
 
+// to rasterize the badges
+extern crate image;
+extern crate rusttype;
+
 // Next, we need to _register_ the other modules of this crate here, using the `mod` keyword:
-// We want it to use the handlers, helpers, github and clippy modules (all in their
-// respective files)
+// We want it to use the handlers, helpers, badge, github, clippy, diagnostics,
+// history, lints, patch, raster, record, reports, stream, thresholds, threads, variants and waiting
+// modules (all in their respective files)
 
 mod handlers;
 mod helpers;
+mod badge;
 mod github;
 mod clippy;
+mod diagnostics;
+mod history;
+mod lints;
+mod patch;
+mod raster;
+mod record;
+mod reports;
+mod stream;
+mod thresholds;
+mod threads;
+mod variants;
+mod waiting;
 
 // Then we  _import_ the things specifically needed for this particular module
 // again starting with iron, its specifics and lastly common libs
 use iron::prelude::*;
+use iron::Protocol;
 use staticfile::Static;
 use mount::Mount;
 
@@ -70,11 +93,32 @@ fn main() {
     //   METHOD "URL/:with_keywords" => HANDLER
     // ```
     mount.mount("/github/", router!(
+        get "/sha/:user/:repo/:sha/log/stream" => handlers::github_log_stream,
+        get "/sha/:user/:repo/:sha/badge/:category" => handlers::github_handler,
         get "/sha/:user/:repo/:sha/:method" => handlers::github_handler,
+        // proxies checking whether their copy is still fresh may ask with `HEAD`
+        head "/sha/:user/:repo/:sha/badge/:category" => handlers::github_handler,
+        head "/sha/:user/:repo/:sha/:method" => handlers::github_handler,
+        get "/:user/:repo/compare/:range/:method" => handlers::github_compare,
+        get "/:user/:repo/:branch/log/stream" => handlers::github_log_stream,
+        get "/:user/:repo/log/stream" => handlers::github_log_stream,
+        get "/:user/:repo/:branch/history.json" => handlers::github_history,
+        get "/:user/:repo/:branch/trend.svg" => handlers::github_trend,
+        get "/:user/:repo/history.json" => handlers::github_history,
+        get "/:user/:repo/trend.svg" => handlers::github_trend,
+        get "/:user/:repo/badge/:category" => handlers::github_finder,
+        get "/:user/:repo/:branch/badge/:category" => handlers::github_finder,
         get "/:user/:repo/:branch/:method" => handlers::github_finder,
-        get "/:user/:repo/:method" => handlers::github_finder
+        get "/:user/:repo/:method" => handlers::github_finder,
+        head "/:user/:repo/badge/:category" => handlers::github_finder,
+        head "/:user/:repo/:branch/badge/:category" => handlers::github_finder,
+        head "/:user/:repo/:branch/:method" => handlers::github_finder,
+        head "/:user/:repo/:method" => handlers::github_finder
     ));
 
+    // The badge builder on the front page asks which variants of badges there are
+    mount.mount("/badges.json", handlers::badge_variants);
+
     // Secondly we have some static files in the static/ folder we'd like to have served.
     // *Note*: We have to define them seperately as Static _does not_ serve recursively
     // at the time of writing.
@@ -86,6 +130,6 @@ fn main() {
     warn!("Server running at 5000");
 
     // And start serving those routes
-    // On port `5000` of all interfaces
-    Iron::new(mount).http("0.0.0.0:5000").unwrap();
+    // On port `5000` of all interfaces, with as many threads as configured
+    Iron::new(mount).listen_with("0.0.0.0:5000", threads::count(), Protocol::Http).unwrap();
 }
@@ -0,0 +1,309 @@
+// Apply clippy's suggestions to the sources and render them as a patch
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::vec::Vec;
+
+use diagnostics::{Diagnostic, Span};
+
+// How many lines of unchanged context to show around every change
+const CONTEXT: usize = 3;
+
+// A single replacement, located by byte offsets into the original file
+struct Edit {
+    start: usize,
+    end: usize,
+    replacement: String,
+}
+
+// Only suggestions the compiler marks as `MachineApplicable` are safe to
+// apply without a human looking at them. These can be found in the spans
+// of the diagnostic and its `help` children.
+fn suggestions(diagnostic: &Diagnostic) -> Vec<&Span> {
+    let mut spans: Vec<&Span> = vec![];
+    for candidate in diagnostic.children.iter().flat_map(|c| c.spans.iter()).chain(diagnostic.spans.iter()) {
+        if candidate.suggested_replacement.is_some() &&
+           candidate.suggestion_applicability.as_ref().map(|a| a.as_str()) == Some("MachineApplicable") {
+            spans.push(candidate);
+        }
+    }
+    spans
+}
+
+// Split the content into lines, keeping the line endings, so
+// we can put the file together exactly the way it was.
+fn split_lines(content: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (index, c) in content.char_indices() {
+        if c == '\n' {
+            lines.push(&content[start..index + 1]);
+            start = index + 1;
+        }
+    }
+    if start < content.len() {
+        lines.push(&content[start..]);
+    }
+    lines
+}
+
+// The byte offset at which each of the lines starts
+fn line_starts(lines: &[&str]) -> Vec<usize> {
+    let mut starts: Vec<usize> = vec![];
+    let mut position = 0;
+    for line in lines {
+        starts.push(position);
+        position += line.len();
+    }
+    starts
+}
+
+// The compiler reports lines and columns (counted in characters, starting at 1)
+// next to the byte offsets. We use the former, as those are reliably relative
+// to the file itself.
+fn offset(lines: &[&str], line_starts: &[usize], line: u64, column: u64) -> Option<usize> {
+    let index = (line as usize).wrapping_sub(1);
+    if index >= lines.len() {
+        return None;
+    }
+    let column = (column as usize).saturating_sub(1);
+    let line_text = lines[index];
+    let in_line = line_text.char_indices().map(|(i, _)| i).nth(column).unwrap_or(line_text.len());
+    Some(line_starts[index] + in_line)
+}
+
+// Render one line of the diff, marking a missing newline at the end of the file
+fn diff_line(out: &mut String, marker: char, line: &str) {
+    out.push(marker);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+// ## Diff File
+// Apply the (sorted, non-overlapping) edits to the content and render the
+// unified diff hunks for the changes.
+fn diff_file(content: &str, edits: &[Edit]) -> String {
+    let lines = split_lines(content);
+    let line_starts = line_starts(&lines);
+    let line_of = |offset: usize| {
+        match line_starts.iter().rposition(|start| *start <= offset) {
+            Some(index) => index,
+            None => 0,
+        }
+    };
+
+    // First group the edits into blocks of changed lines: edits touching the same
+    // lines are in the same block. For every block, we keep the range of old lines
+    // (inclusive) and the new lines replacing them.
+    let mut blocks: Vec<(usize, usize, Vec<&Edit>)> = vec![];
+    for edit in edits {
+        let first = line_of(edit.start);
+        let last = if edit.end > edit.start { line_of(edit.end - 1) } else { first };
+        let extends_previous = match blocks.last() {
+            Some(&(_, previous_last, _)) => first <= previous_last,
+            None => false,
+        };
+        if extends_previous {
+            let block = blocks.last_mut().unwrap();
+            if last > block.1 {
+                block.1 = last;
+            }
+            block.2.push(edit);
+        } else {
+            blocks.push((first, last, vec![edit]));
+        }
+    }
+
+    let changes: Vec<(usize, usize, String)> = blocks.iter()
+        .map(|&(first, last, ref edits)| {
+            let block_start = line_starts[first];
+            let block_end = line_starts[last] + lines[last].len();
+            let mut new_text = String::new();
+            let mut position = block_start;
+            for edit in edits {
+                new_text.push_str(&content[position..edit.start]);
+                new_text.push_str(&edit.replacement);
+                position = edit.end;
+            }
+            new_text.push_str(&content[position..block_end]);
+            (first, last, new_text)
+        })
+        .collect();
+
+    // Then put the changes into hunks, merging them if their context overlaps
+    let mut out = String::new();
+    let mut line_delta: isize = 0;
+    let mut index = 0;
+    while index < changes.len() {
+        let mut end = index;
+        while end + 1 < changes.len() && changes[end + 1].0 <= changes[end].1 + 1 + 2 * CONTEXT {
+            end += 1;
+        }
+
+        let hunk_start = changes[index].0.saturating_sub(CONTEXT);
+        let hunk_end = ::std::cmp::min(lines.len(), changes[end].1 + 1 + CONTEXT);
+        let mut body = String::new();
+        let mut old_count = 0;
+        let mut new_count = 0;
+        let mut line = hunk_start;
+        for &(first, last, ref new_text) in &changes[index..end + 1] {
+            while line < first {
+                diff_line(&mut body, ' ', lines[line]);
+                line += 1;
+                old_count += 1;
+                new_count += 1;
+            }
+            for old in &lines[first..last + 1] {
+                diff_line(&mut body, '-', old);
+                old_count += 1;
+            }
+            for new in split_lines(new_text) {
+                diff_line(&mut body, '+', new);
+                new_count += 1;
+            }
+            line = last + 1;
+        }
+        while line < hunk_end {
+            diff_line(&mut body, ' ', lines[line]);
+            line += 1;
+            old_count += 1;
+            new_count += 1;
+        }
+
+        let new_start = hunk_start as isize + 1 + line_delta;
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n", hunk_start + 1, old_count, new_start, new_count));
+        out.push_str(&body);
+        line_delta += new_count as isize - old_count as isize;
+        index = end + 1;
+    }
+    out
+}
+
+// ## Generate
+// Collect all machine applicable suggestions of the diagnostics, apply them to
+// the sources of the crate found in `crate_dir` and return the changes as
+// unified diff, ready for `git apply`. The paths in the patch are relative to
+// the root of the repository, in which the crate lives at `prefix`.
+pub fn generate(crate_dir: &Path, prefix: &Path, diagnostics: &[Diagnostic]) -> String {
+    let mut files: Vec<(String, Vec<&Span>)> = vec![];
+    for span in diagnostics.iter().flat_map(|d| suggestions(d).into_iter()) {
+        // Suggestions for code outside of the crate (like dependencies) can't be applied
+        if span.file_name.starts_with('/') || span.file_name.contains("..") {
+            continue;
+        }
+        match files.iter().position(|&(ref name, _)| name == &span.file_name) {
+            Some(index) => files[index].1.push(span),
+            None => files.push((span.file_name.clone(), vec![span])),
+        }
+    }
+
+    let mut patch = String::new();
+    for (file_name, spans) in files {
+        let mut content = String::new();
+        let readable = File::open(crate_dir.join(&file_name))
+                           .and_then(|mut file| file.read_to_string(&mut content))
+                           .is_ok();
+        if !readable {
+            continue;
+        }
+
+        let lines = split_lines(&content);
+        let line_starts = line_starts(&lines);
+
+        let mut edits: Vec<Edit> = spans.iter()
+            .filter_map(|span| {
+                match (offset(&lines, &line_starts, span.line_start, span.column_start),
+                       offset(&lines, &line_starts, span.line_end, span.column_end)) {
+                    (Some(start), Some(end)) if start <= end => {
+                        Some(Edit {
+                            start: start,
+                            end: end,
+                            replacement: span.suggested_replacement.clone().unwrap(),
+                        })
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+
+        // Apply the edits in order. If two suggestions overlap, we can only
+        // apply one of them, so the later one is dropped.
+        edits.sort_by(|a, b| (a.start, a.end).cmp(&(b.start, b.end)));
+        let mut applicable: Vec<Edit> = vec![];
+        for edit in edits {
+            let overlaps = match applicable.last() {
+                Some(previous) => edit.start < previous.end || edit.start == previous.start,
+                None => false,
+            };
+            if !overlaps {
+                applicable.push(edit);
+            }
+        }
+        if applicable.is_empty() {
+            continue;
+        }
+
+        let path = prefix.join(&file_name).to_string_lossy().into_owned();
+        patch.push_str(&format!("diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n", path));
+        patch.push_str(&diff_file(&content, &applicable));
+    }
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Edit, diff_file, line_starts, offset, split_lines};
+
+    // Replace the characters from `column_start` to `column_end` on `line`
+    fn edit(content: &str, line: u64, column_start: u64, column_end: u64, replacement: &str) -> Edit {
+        let lines = split_lines(content);
+        let line_starts = line_starts(&lines);
+        Edit {
+            start: offset(&lines, &line_starts, line, column_start).unwrap(),
+            end: offset(&lines, &line_starts, line, column_end).unwrap(),
+            replacement: replacement.to_owned(),
+        }
+    }
+
+    fn numbered(count: usize) -> String {
+        (1..count + 1).map(|line| format!("line {}\n", line)).collect()
+    }
+
+    #[test]
+    fn two_edits_on_one_line() {
+        let content = "fn main() {\n    let x = a + b;\n}\n";
+        let edits = [edit(content, 2, 13, 14, "1"), edit(content, 2, 17, 18, "2")];
+        assert_eq!(diff_file(content, &edits),
+                   "@@ -1,3 +1,3 @@\n fn main() {\n-    let x = a + b;\n+    let x = 1 + 2;\n }\n");
+    }
+
+    #[test]
+    fn close_edits_share_a_hunk() {
+        let content = numbered(10);
+        let edits = [edit(&content, 2, 6, 7, "two"), edit(&content, 8, 6, 7, "eight")];
+        assert_eq!(diff_file(&content, &edits),
+                   "@@ -1,10 +1,10 @@\n line 1\n-line 2\n+line two\n line 3\n line 4\n line 5\n line 6\n \
+                    line 7\n-line 8\n+line eight\n line 9\n line 10\n");
+    }
+
+    #[test]
+    fn added_lines_move_later_hunks() {
+        let content = numbered(20);
+        let edits = [edit(&content, 2, 1, 7, "a\nb\nc"), edit(&content, 15, 6, 8, "fifteen")];
+        assert_eq!(diff_file(&content, &edits),
+                   "@@ -1,5 +1,7 @@\n line 1\n-line 2\n+a\n+b\n+c\n line 3\n line 4\n line 5\n\
+                    @@ -12,7 +14,7 @@\n line 12\n line 13\n line 14\n-line 15\n+line fifteen\n line 16\n \
+                    line 17\n line 18\n");
+    }
+
+    #[test]
+    fn missing_newline_at_end_of_file() {
+        let content = "a\nb";
+        let edits = [edit(content, 2, 1, 2, "c")];
+        assert_eq!(diff_file(content, &edits),
+                   "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n\\ No newline at end of file\n");
+    }
+}
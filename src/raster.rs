@@ -0,0 +1,195 @@
+// Rasterize the badges for the places that can't show SVG
+
+extern crate image;
+extern crate rusttype;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+use image::{ImageBuffer, ImageFormat, ImageRgba8, Rgba};
+use rusttype::{Font, FontCollection, Scale, point};
+
+use badge::{Badge, Stop, rgb, text_colors};
+
+// We don't have Verdana around, so we set the badges in DejaVu Sans,
+// as found in Debians `fonts-dejavu-core`. Set `CLIPPY_BADGE_FONT`
+// to the path of another TrueType font to use that instead. Badges with
+// characters the font doesn't have, like emoji, are drawn by shields.io.
+static DEFAULT_FONT: &'static str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+
+// rusttype scales the glyphs so that ascent to descent fill the scale,
+// while font sizes are given as the size of the em. For DejaVu Sans,
+// that's a factor of:
+static EM_FACTOR: f64 = 1.164;
+
+type Canvas = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+fn load_font() -> Result<Font<'static>, String> {
+    let path = env::var("CLIPPY_BADGE_FONT").unwrap_or(String::from(DEFAULT_FONT));
+    let mut data: Vec<u8> = vec![];
+    if let Err(error) = File::open(&path).and_then(|mut file| file.read_to_end(&mut data)) {
+        return Err(format!("Can't read font {}: {}", path, error));
+    }
+    match FontCollection::from_bytes(data).into_font() {
+        Some(font) => Ok(font),
+        None => Err(format!("No font found in {}", path)),
+    }
+}
+
+// How much of the pixel centered at `x`, `y` is covered by the badge,
+// a rectangle with rounded corners. Only in the corners this isn't all
+// of it, there we anti-alias the curve.
+fn coverage(x: f64, y: f64, width: f64, height: f64, radius: f64) -> f64 {
+    let corner_x = if x < radius {
+        radius
+    } else if x > width - radius {
+        width - radius
+    } else {
+        return 1.0;
+    };
+    let corner_y = if y < radius {
+        radius
+    } else if y > height - radius {
+        height - radius
+    } else {
+        return 1.0;
+    };
+    let distance = ((x - corner_x).powi(2) + (y - corner_y).powi(2)).sqrt();
+    (radius - distance + 0.5).max(0.0).min(1.0)
+}
+
+// Lay the gradient, at the relative height `at`, over the color
+fn overlay(color: (u8, u8, u8), stops: &[Stop], at: f64) -> (u8, u8, u8) {
+    if stops.is_empty() {
+        return color;
+    }
+    let (from, to) = match stops.iter().position(|&(offset, _, _)| offset >= at) {
+        Some(0) => (stops[0], stops[0]),
+        Some(index) => (stops[index - 1], stops[index]),
+        None => (stops[stops.len() - 1], stops[stops.len() - 1]),
+    };
+    let share = if to.0 > from.0 { (at - from.0) / (to.0 - from.0) } else { 0.0 };
+    let (start, end) = (rgb(from.1), rgb(to.1));
+    let opacity = from.2 + (to.2 - from.2) * share;
+    let blend = |base: u8, start: u8, end: u8| {
+        let over = start as f64 + (end as f64 - start as f64) * share;
+        (base as f64 * (1.0 - opacity) + over * opacity).round() as u8
+    };
+    (blend(color.0, start.0, end.0), blend(color.1, start.1, end.1), blend(color.2, start.2, end.2))
+}
+
+// Draw the text centered at `center`, squeezed into `length` – just like
+// the `textLength` of the SVG does.
+fn draw_text(canvas: &mut Canvas,
+             font: &Font,
+             text: &str,
+             center: f64,
+             length: f64,
+             baseline: f64,
+             size: f64,
+             fill: &str,
+             opacity: f64) {
+    let height = (size * EM_FACTOR) as f32;
+    let natural = font.layout(text, Scale::uniform(height), point(0.0, 0.0))
+                      .last()
+                      .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+                      .unwrap_or(0.0);
+    if natural <= 0.0 {
+        return;
+    }
+    let scale = Scale {
+        x: height * length as f32 / natural,
+        y: height,
+    };
+    let fill = rgb(fill);
+    let (width, canvas_height) = (canvas.width() as i32, canvas.height() as i32);
+    for glyph in font.layout(text, scale, point((center - length / 2.0) as f32, baseline as f32)) {
+        if let Some(bounds) = glyph.pixel_bounding_box() {
+            glyph.draw(|x, y, value| {
+                let (x, y) = (bounds.min.x + x as i32, bounds.min.y + y as i32);
+                if x < 0 || y < 0 || x >= width || y >= canvas_height {
+                    return;
+                }
+                let alpha = value as f64 * opacity;
+                let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+                for (channel, over) in [fill.0, fill.1, fill.2].iter().enumerate() {
+                    let base = pixel.data[channel] as f64;
+                    pixel.data[channel] = (base * (1.0 - alpha) + *over as f64 * alpha).round() as u8;
+                }
+            });
+        }
+    }
+}
+
+// ## PNG
+// Rasterize the badge at the given scale – 2 for high resolution
+// displays – and encode it as PNG. We draw the badge from the same layout
+// its SVG is made of; logos are left out though, as are the speech bubbles
+// of the social style, which comes out flat.
+pub fn png(badge: &Badge, scale: u32) -> Result<Vec<u8>, String> {
+    let font = match load_font() {
+        Ok(font) => font,
+        Err(error) => return Err(error),
+    };
+    let layout = badge.layout();
+    // Emoji and the like aren't in the font, we leave those to shields.io
+    let missing = layout.label.chars().chain(layout.message.chars()).find(|&c| font.glyph(c).is_none());
+    if let Some(missing) = missing {
+        return Err(format!("The font has no glyph for {}", missing));
+    }
+    let metrics = &layout.metrics;
+    let scale = scale as f64;
+    let (width, height) = (layout.width() * scale, metrics.height * scale);
+
+    let mut canvas: Canvas = ImageBuffer::new(width.ceil() as u32, height.ceil() as u32);
+    let (label_color, message_color) = (rgb(&layout.label_color), rgb(&layout.message_color));
+    for (x, y, pixel) in canvas.enumerate_pixels_mut() {
+        let (x, y) = (x as f64 + 0.5, y as f64 + 0.5);
+        let coverage = coverage(x, y, width, height, metrics.radius * scale);
+        if coverage <= 0.0 {
+            continue;
+        }
+        let background = if x < layout.label_width * scale { label_color } else { message_color };
+        let (red, green, blue) = overlay(background, metrics.gradient, y / height);
+        *pixel = Rgba([red, green, blue, (coverage * 255.0).round() as u8]);
+    }
+
+    let size = metrics.font_size as f64 / 10.0 * scale;
+    let mut texts = vec![(&layout.message,
+                          layout.message_center(),
+                          layout.message_text_width,
+                          &layout.message_color)];
+    if !layout.label.is_empty() {
+        texts.push((&layout.label, layout.label_center(), layout.label_text_width, &layout.label_color));
+    }
+    for (text, center, length, background) in texts {
+        let (fill, shadow) = text_colors(background);
+        if metrics.shadow {
+            draw_text(&mut canvas,
+                      &font,
+                      text,
+                      center * scale,
+                      length * scale,
+                      (metrics.baseline + 1.0) * scale,
+                      size,
+                      shadow,
+                      0.3);
+        }
+        draw_text(&mut canvas,
+                  &font,
+                  text,
+                  center * scale,
+                  length * scale,
+                  metrics.baseline * scale,
+                  size,
+                  fill,
+                  1.0);
+    }
+
+    let mut png: Vec<u8> = vec![];
+    match ImageRgba8(canvas).save(&mut png, ImageFormat::PNG) {
+        Ok(_) => Ok(png),
+        Err(error) => Err(format!("Encoding the badge failed: {}", error)),
+    }
+}
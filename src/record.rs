@@ -0,0 +1,175 @@
+// The result of a clippy run, as we store it in redis
+
+extern crate rustc_serialize;
+
+use rustc_serialize::json;
+
+// Bump this whenever the fields of the record change. Results stored
+// as plain status strings – before there were records – are version 0.
+pub static RECORD_VERSION: u32 = 1;
+
+// ## State
+// What came out of a run – or that it is still going on.
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Clone, Copy, Debug)]
+pub enum State {
+    Linting,
+    Success,
+    Warnings,
+    LintErrors,
+    BuildFailed,
+    Ice,
+    Failed,
+}
+
+impl State {
+    // ### Name
+    // The state as it's told to scripts, e.g. `lint_errors`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Linting => "linting",
+            State::Success => "success",
+            State::Warnings => "warnings",
+            State::LintErrors => "lint_errors",
+            State::BuildFailed => "build_failed",
+            State::Ice => "ice",
+            State::Failed => "failed",
+        }
+    }
+}
+
+// ## Durations
+// How long the parts of the run took, in milliseconds
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Durations {
+    pub download: Option<u64>,
+    pub lint: Option<u64>,
+    pub total: Option<u64>,
+}
+
+// ## Clippy Record
+// Everything we know about the result of a run. The counts only include new
+// diagnostics – the ones in the projects baseline are counted separately –
+// and are the unique ones, `raw` is how often they were actually reported.
+// `message` holds the panic message of an internal compiler error or the
+// reason the run failed.
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct ClippyRecord {
+    pub version: u32,
+    pub state: State,
+    pub warnings: u32,
+    pub errors: u32,
+    pub baselined: u32,
+    pub raw: u32,
+    pub color: String,
+    pub message: Option<String>,
+    pub toolchain: Option<String>,
+    pub clippy_version: Option<String>,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub durations: Durations,
+}
+
+impl ClippyRecord {
+    // ### New
+    // A record of the given state and color, with nothing else known yet
+    pub fn new(state: State, color: &str) -> ClippyRecord {
+        ClippyRecord {
+            version: RECORD_VERSION,
+            state: state,
+            warnings: 0,
+            errors: 0,
+            baselined: 0,
+            raw: 0,
+            color: color.to_owned(),
+            message: None,
+            toolchain: None,
+            clippy_version: None,
+            started_at: None,
+            finished_at: None,
+            durations: Durations {
+                download: None,
+                lint: None,
+                total: None,
+            },
+        }
+    }
+
+    // ### Linting
+    // What to show while we don't have a result yet
+    pub fn linting() -> ClippyRecord {
+        ClippyRecord::new(State::Linting, "blue")
+    }
+
+    // ### Text
+    // The status as shown in the badges, e.g. `3 warnings`
+    pub fn text(&self) -> String {
+        match self.state {
+            State::Linting => String::from("linting"),
+            State::Success if self.baselined > 0 => format!("0 new ({0} baselined)", self.baselined),
+            State::Success => String::from("success"),
+            State::Warnings if self.baselined > 0 => {
+                format!("{0} new ({1} baselined)", self.warnings, self.baselined)
+            }
+            State::Warnings => format!("{0} warnings", self.warnings),
+            State::LintErrors if self.baselined > 0 => {
+                format!("{0} new lint errors ({1} baselined)", self.errors, self.baselined)
+            }
+            State::LintErrors => format!("{0} lint errors", self.errors),
+            State::BuildFailed => String::from("build failed"),
+            State::Ice => String::from("ice"),
+            State::Failed => String::from("failed"),
+        }
+    }
+
+    // ### Is Done
+    // Whether the run finished, successfully or not
+    pub fn is_done(&self) -> bool {
+        self.state != State::Linting
+    }
+
+    // ### Decode
+    // Read a stored record. Results stored before we had records are plain
+    // status strings, which we convert with `from_legacy`. Records we can't
+    // read – or written in another version – are `None`, to be linted again.
+    pub fn decode(data: &str) -> Option<ClippyRecord> {
+        if !data.starts_with('{') {
+            return ClippyRecord::from_legacy(data);
+        }
+        match json::decode::<ClippyRecord>(data) {
+            Ok(ref record) if record.version != RECORD_VERSION => None,
+            Ok(record) => Some(record),
+            Err(_) => None,
+        }
+    }
+
+    // ### From Legacy
+    // Parse the status strings we used to store – `success`, `failed`,
+    // `3 warnings` or `2 errors` – into a record.
+    pub fn from_legacy(status: &str) -> Option<ClippyRecord> {
+        let mut parts = status.splitn(2, ' ');
+        let (first, second) = (parts.next().unwrap_or(""), parts.next());
+        let mut record = match (first, second) {
+            ("success", None) => ClippyRecord::new(State::Success, "brightgreen"),
+            ("failed", None) => ClippyRecord::new(State::Failed, "red"),
+            (count, Some("warnings")) => {
+                let mut record = ClippyRecord::new(State::Warnings, "yellow");
+                record.warnings = match count.parse::<u32>() {
+                    Ok(count) => count,
+                    Err(_) => return None,
+                };
+                record
+            }
+            (count, Some("errors")) => {
+                let mut record = ClippyRecord::new(State::LintErrors, "red");
+                record.errors = match count.parse::<u32>() {
+                    Ok(count) => count,
+                    Err(_) => return None,
+                };
+                record
+            }
+            _ => return None,
+        };
+        record.version = 0;
+        Some(record)
+    }
+}
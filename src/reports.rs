@@ -0,0 +1,419 @@
+// Render the stored diagnostics into report formats other tools understand
+
+extern crate rustc_serialize;
+
+use std::collections::BTreeMap;
+use std::vec::Vec;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use diagnostics::{Diagnostic, Span, Comparison, Kind};
+use lints::Breakdown;
+use history::Entry;
+use helpers::xml_escape;
+use variants::BadgeRenderer;
+use record::ClippyRecord;
+
+// Where to find the documentation for a lint, we append the lint name –
+// and for an error of rustc, we append the error code.
+static LINT_DOCS_URL: &'static str = "https://rust-lang.github.io/rust-clippy/master/index.html#";
+static ERROR_DOCS_URL: &'static str = "https://doc.rust-lang.org/error-index.html#";
+
+// rustc_serialize doesn't have a literal syntax for JSON, this little
+// helper builds objects from a list of key-value-pairs instead.
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    let mut map = BTreeMap::new();
+    for (key, value) in fields {
+        map.insert(key.to_owned(), value);
+    }
+    Json::Object(map)
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_owned())
+}
+
+// The compiler reports paths relative to the crate, which may well be in a
+// folder of the repository. Tools importing the reports want them relative
+// to the root of the repository, so we put the `prefix` to the crate in front.
+fn repository_path(prefix: &str, file_name: &str) -> String {
+    match prefix {
+        "" => file_name.to_owned(),
+        _ => format!("{}/{}", prefix.trim_right_matches('/'), file_name),
+    }
+}
+
+// Both XML formats are organised by file, so group the diagnostics by the
+// file of their primary span – keeping the order in which they appeared.
+// Diagnostics without any span are about the crate as a whole.
+fn by_file<'a>(diagnostics: &'a [Diagnostic], prefix: &str) -> Vec<(String, Vec<&'a Diagnostic>)> {
+    let mut files: Vec<(String, Vec<&Diagnostic>)> = vec![];
+    for diagnostic in diagnostics {
+        let file_name = diagnostic.primary_span()
+                                  .map(|span| repository_path(prefix, &span.file_name))
+                                  .unwrap_or(match prefix {
+                                      "" => String::from("."),
+                                      _ => prefix.to_owned(),
+                                  });
+        match files.iter().position(|&(ref name, _)| name == &file_name) {
+            Some(index) => files[index].1.push(diagnostic),
+            None => files.push((file_name, vec![diagnostic])),
+        }
+    }
+    files
+}
+
+// The lint name, or `rustc` and the error code for plain compiler messages
+fn source_name(diagnostic: &Diagnostic) -> String {
+    match (&diagnostic.lint, &diagnostic.error_code) {
+        (&Some(ref lint), _) => format!("clippy.{}", lint),
+        (&None, &Some(ref code)) => format!("rustc.{}", code),
+        (&None, &None) => String::from("rustc"),
+    }
+}
+
+// Every result of SARIF needs a rule: the lint, the error code of rustc –
+// or just `rustc` for the compiler messages that don't have one.
+fn rule_id(diagnostic: &Diagnostic) -> String {
+    match (&diagnostic.lint, &diagnostic.error_code) {
+        (&Some(ref lint), _) => lint.clone(),
+        (&None, &Some(ref code)) => code.clone(),
+        (&None, &None) => String::from("rustc"),
+    }
+}
+
+fn sarif_rule(diagnostic: &Diagnostic) -> Json {
+    let id = rule_id(diagnostic);
+    let mut fields = vec![("id", string(&id)), ("name", string(&id))];
+    match (&diagnostic.lint, &diagnostic.error_code) {
+        (&Some(ref lint), _) => fields.push(("helpUri", Json::String(format!("{}{}", LINT_DOCS_URL, lint)))),
+        (&None, &Some(ref code)) => fields.push(("helpUri", Json::String(format!("{}{}", ERROR_DOCS_URL, code)))),
+        (&None, &None) => {}
+    }
+    object(fields)
+}
+
+// ## SARIF
+// Convert the diagnostics into a [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/)
+// log, as understood by most code-scanning tools. Every lint becomes a rule of the
+// `clippy` tool – as does every error code of rustc – every diagnostic a result
+// pointing at its primary span, and suggestions are passed on as fixes. Paths
+// are relative to the repository, the crate being at `prefix` in there.
+pub fn sarif(diagnostics: &[Diagnostic], prefix: &str) -> Json {
+    // Collect the rules in order of appearance, so we can refer
+    // to them by index from the results.
+    let mut rules: Vec<(String, Json)> = vec![];
+    for diagnostic in diagnostics {
+        let id = rule_id(diagnostic);
+        if !rules.iter().any(|&(ref rule, _)| *rule == id) {
+            rules.push((id, sarif_rule(diagnostic)));
+        }
+    }
+
+    let results: Vec<Json> = diagnostics.iter()
+        .map(|diagnostic| {
+            let mut fields = vec![
+                ("level", string(sarif_level(&diagnostic.level))),
+                ("message", object(vec![("text", string(&diagnostic.message))])),
+                ("locations", Json::Array(diagnostic.primary_span()
+                                                    .map(|span| vec![sarif_location(span, prefix)])
+                                                    .unwrap_or(vec![]))),
+                ("fixes", Json::Array(sarif_fixes(diagnostic, prefix))),
+            ];
+            // Diagnostics known from the projects baseline aren't new
+            fields.push(("baselineState",
+                         string(if diagnostic.baselined { "unchanged" } else { "new" })));
+            let id = rule_id(diagnostic);
+            let index = rules.iter().position(|&(ref rule, _)| *rule == id).unwrap_or(0);
+            fields.push(("ruleId", string(&id)));
+            fields.push(("ruleIndex", Json::U64(index as u64)));
+            object(fields)
+        })
+        .collect();
+
+    let rules: Vec<Json> = rules.into_iter().map(|(_, rule)| rule).collect();
+
+    object(vec![
+        ("$schema", string("https://json.schemastore.org/sarif-2.1.0.json")),
+        ("version", string("2.1.0")),
+        ("runs", Json::Array(vec![object(vec![
+            ("tool", object(vec![
+                ("driver", object(vec![
+                    ("name", string("clippy")),
+                    ("informationUri", string("https://github.com/rust-lang/rust-clippy")),
+                    ("rules", Json::Array(rules)),
+                ])),
+            ])),
+            ("results", Json::Array(results)),
+        ])])),
+    ])
+}
+
+// SARIF only knows `error`, `warning` and `note`, the compiler also
+// reports `help` and `failure-note`, which we treat as notes.
+fn sarif_level(level: &str) -> &'static str {
+    match level {
+        "error" => "error",
+        "warning" => "warning",
+        _ => "note",
+    }
+}
+
+fn sarif_region(span: &Span) -> Json {
+    object(vec![
+        ("startLine", Json::U64(span.line_start)),
+        ("startColumn", Json::U64(span.column_start)),
+        ("endLine", Json::U64(span.line_end)),
+        ("endColumn", Json::U64(span.column_end)),
+    ])
+}
+
+fn sarif_artifact(span: &Span, prefix: &str) -> Json {
+    object(vec![
+        ("uri", string(&repository_path(prefix, &span.file_name))),
+        ("uriBaseId", string("%SRCROOT%")),
+    ])
+}
+
+fn sarif_location(span: &Span, prefix: &str) -> Json {
+    object(vec![
+        ("physicalLocation", object(vec![
+            ("artifactLocation", sarif_artifact(span, prefix)),
+            ("region", sarif_region(span)),
+        ])),
+    ])
+}
+
+// Suggestions live in the spans of the `help` children of a diagnostic.
+// Each child with replacements becomes one fix.
+fn sarif_fixes(diagnostic: &Diagnostic, prefix: &str) -> Vec<Json> {
+    diagnostic.children
+              .iter()
+              .filter_map(|child| {
+                  let changes: Vec<Json> = child.spans
+                      .iter()
+                      .filter_map(|span| {
+                          span.suggested_replacement.as_ref().map(|replacement| {
+                              object(vec![
+                                  ("artifactLocation", sarif_artifact(span, prefix)),
+                                  ("replacements", Json::Array(vec![object(vec![
+                                      ("deletedRegion", sarif_region(span)),
+                                      ("insertedContent", object(vec![("text", string(replacement))])),
+                                  ])])),
+                              ])
+                          })
+                      })
+                      .collect();
+                  if changes.is_empty() {
+                      None
+                  } else {
+                      Some(object(vec![
+                          ("description", object(vec![("text", string(&child.message))])),
+                          ("artifactChanges", Json::Array(changes)),
+                      ]))
+                  }
+              })
+              .collect()
+}
+
+// ## Breakdown
+// The counts per lint and per category, as calculated by `lints::breakdown`,
+// and whether clippy's categories were known to calculate them with
+pub fn breakdown(breakdown: &Breakdown) -> Json {
+    object(vec![
+        ("total", Json::U64(breakdown.total as u64)),
+        ("raw", Json::U64(breakdown.raw as u64)),
+        ("lints", breakdown.lints.to_json()),
+        ("categories", breakdown.categories.to_json()),
+        ("groups_known", Json::Boolean(breakdown.groups_known)),
+    ])
+}
+
+// ## Comparison
+// List the diagnostics introduced, fixed and unchanged between two commits,
+// each with the lint, level, message and location.
+pub fn comparison(base: &str, head: &str, comparison: &Comparison) -> Json {
+    let summarize = |diagnostics: &[Diagnostic]| {
+        Json::Array(diagnostics.iter()
+            .map(|diagnostic| {
+                let mut fields = vec![
+                    ("level", string(&diagnostic.level)),
+                    ("message", string(&diagnostic.message)),
+                    ("lint", diagnostic.lint.as_ref().map(|l| string(l)).unwrap_or(Json::Null)),
+                ];
+                if let Some(span) = diagnostic.primary_span() {
+                    fields.push(("file", string(&span.file_name)));
+                    fields.push(("line", Json::U64(span.line_start)));
+                    fields.push(("column", Json::U64(span.column_start)));
+                }
+                object(fields)
+            })
+            .collect())
+    };
+    object(vec![
+        ("base", string(base)),
+        ("head", string(head)),
+        ("introduced", summarize(&comparison.introduced)),
+        ("fixed", summarize(&comparison.fixed)),
+        ("unchanged", summarize(&comparison.unchanged)),
+    ])
+}
+
+// ## History
+// The heads of a branch over time with their status and counts, if known
+pub fn history(entries: &[(Entry, String, Option<(u32, u32)>)]) -> Json {
+    Json::Array(entries.iter()
+        .map(|&(ref entry, ref status, ref counts)| {
+            let mut fields = vec![
+                ("sha", string(&entry.sha)),
+                ("timestamp", string(&entry.timestamp)),
+                ("status", string(status)),
+            ];
+            if let Some((warnings, errors)) = *counts {
+                fields.push(("warnings", Json::U64(warnings as u64)));
+                fields.push(("errors", Json::U64(errors as u64)));
+            }
+            object(fields)
+        })
+        .collect())
+}
+
+// ## Baseline
+// The fingerprints of all lints found, to be committed as the baseline of
+// the project. Compiler errors need fixing, so they can't be part of it.
+pub fn baseline(diagnostics: &[Diagnostic]) -> Json {
+    let mut fingerprints: Vec<String> = diagnostics.iter()
+        .filter(|diagnostic| {
+            match diagnostic.kind() {
+                Kind::Warning | Kind::LintError => true,
+                _ => false,
+            }
+        })
+        .map(|diagnostic| diagnostic.fingerprint())
+        .collect();
+    fingerprints.sort();
+    Json::Array(fingerprints.into_iter().map(Json::String).collect())
+}
+
+// ## Shields
+// The badge in the [endpoint schema](https://shields.io/endpoint) of shields.io,
+// for anyone who wants to render the badge their own way. `cacheSeconds` tells
+// the renderer how long it may keep it, errors are marked as such.
+pub fn shields(label: &str, message: &str, color: &str, cache_seconds: u64, is_error: bool) -> Json {
+    object(vec![("schemaVersion", Json::U64(1)),
+                ("label", string(label)),
+                ("message", string(message)),
+                ("color", string(color)),
+                ("cacheSeconds", Json::U64(cache_seconds)),
+                ("isError", Json::Boolean(is_error))])
+}
+
+// ## Status
+// The result of a commit for scripts: the state of the run, what it found –
+// the unique warnings and errors as well as how often lints were reported in
+// total, `raw` – what it ran with and where to read more: the log and the report.
+pub fn status(record: &ClippyRecord, sha: &str, log_url: &str, report_url: &str) -> Json {
+    let optional = |value: &Option<String>| value.as_ref().map(|value| string(value)).unwrap_or(Json::Null);
+    object(vec![
+        ("state", string(record.state.name())),
+        ("status", string(&record.text())),
+        ("warnings", Json::U64(record.warnings as u64)),
+        ("errors", Json::U64(record.errors as u64)),
+        ("raw", Json::U64(record.raw as u64)),
+        ("baselined", Json::U64(record.baselined as u64)),
+        ("color", string(&record.color)),
+        ("sha", string(sha)),
+        ("toolchain", optional(&record.toolchain)),
+        ("clippy_version", optional(&record.clippy_version)),
+        ("message", optional(&record.message)),
+        ("finished_at", optional(&record.finished_at)),
+        ("links", object(vec![("log", string(log_url)), ("report", string(report_url))])),
+    ])
+}
+
+// ## Variants
+// The badge variants registered, with the argument they take, if any
+pub fn variants(renderers: &[Box<BadgeRenderer>]) -> Json {
+    Json::Array(renderers.iter()
+        .map(|renderer| {
+            object(vec![("name", string(renderer.name())),
+                        ("description", string(renderer.description())),
+                        ("argument", renderer.argument().map(string).unwrap_or(Json::Null))])
+        })
+        .collect())
+}
+
+// ## Checkstyle
+// Render the diagnostics as [Checkstyle](http://checkstyle.sourceforge.net/) XML,
+// which Jenkins' warnings plugin and many other CI dashboards can import.
+pub fn checkstyle(diagnostics: &[Diagnostic], prefix: &str) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<checkstyle version=\"4.3\">\n");
+    for (file_name, diagnostics) in by_file(diagnostics, prefix) {
+        out.push_str(&format!("  <file name=\"{}\">\n", xml_escape(&file_name)));
+        for diagnostic in diagnostics {
+            let (line, column) = diagnostic.primary_span()
+                                           .map(|span| (span.line_start, span.column_start))
+                                           .unwrap_or((0, 0));
+            out.push_str(&format!("    <error line=\"{}\" column=\"{}\" severity=\"{}\" \
+                                   message=\"{}\" source=\"{}\" />\n",
+                                  line,
+                                  column,
+                                  checkstyle_severity(&diagnostic.level),
+                                  xml_escape(&diagnostic.message),
+                                  xml_escape(&source_name(diagnostic))));
+        }
+        out.push_str("  </file>\n");
+    }
+    out.push_str("</checkstyle>\n");
+    out
+}
+
+fn checkstyle_severity(level: &str) -> &'static str {
+    match level {
+        "error" => "error",
+        "warning" => "warning",
+        _ => "info",
+    }
+}
+
+// ## JUnit
+// Render the diagnostics as JUnit XML, with one test suite per file and every
+// diagnostic as a failed test case. If there aren't any, we report a single
+// passing test case, so dashboards show a green run rather than an empty one.
+pub fn junit(diagnostics: &[Diagnostic], prefix: &str) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let total = diagnostics.len();
+    out.push_str(&format!("<testsuites name=\"clippy\" tests=\"{0}\" failures=\"{0}\">\n",
+                          total));
+    if total == 0 {
+        out.push_str("  <testsuite name=\"clippy\" tests=\"1\" failures=\"0\">\n    \
+                      <testcase name=\"clippy\" classname=\"clippy\" />\n  </testsuite>\n");
+    }
+    for (file_name, diagnostics) in by_file(diagnostics, prefix) {
+        out.push_str(&format!("  <testsuite name=\"{0}\" tests=\"{1}\" failures=\"{1}\">\n",
+                              xml_escape(&file_name),
+                              diagnostics.len()));
+        for diagnostic in diagnostics {
+            let location = match diagnostic.primary_span() {
+                Some(span) => {
+                    format!("{}:{}:{}",
+                            repository_path(prefix, &span.file_name),
+                            span.line_start,
+                            span.column_start)
+                }
+                None => file_name.clone(),
+            };
+            out.push_str(&format!("    <testcase name=\"{0} at {1}\" classname=\"{2}\">\n      \
+                                   <failure type=\"{3}\" message=\"{4}\">{3}: {4}\n  --> {1}</failure>\n    \
+                                   </testcase>\n",
+                                  xml_escape(&source_name(diagnostic)),
+                                  xml_escape(&location),
+                                  xml_escape(&file_name),
+                                  xml_escape(&diagnostic.level),
+                                  xml_escape(&diagnostic.message)));
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
@@ -0,0 +1,168 @@
+// Stream the log of a run while it's written, as Server-Sent Events
+
+extern crate redis;
+
+use std::cmp;
+use std::io::{self, Read};
+use std::iter;
+use std::thread;
+use std::time::Duration;
+
+use redis::{Commands, RedisResult, Value};
+
+use helpers::{setup_redis, get_record_or};
+use threads::Lease;
+
+// How often we look for new lines of the log – and for how long at most,
+// so a forgotten tab doesn't keep a thread of ours busy forever. Browsers
+// reconnect by themselves and pick up where they left off.
+static POLL_MILLIS: u64 = 1000;
+static MAX_POLLS: u32 = 10 * 60;
+
+// Every so often we send a comment, which is how we learn that the
+// requester has gone away.
+static KEEP_ALIVE_POLLS: u32 = 15;
+
+// Without a lease on a thread, the stream is just the log so far – and a
+// hint to come back in a while, which browsers do by themselves.
+static RETRY_MILLIS: u32 = 30 * 1000;
+
+// ## Log Stream
+// The log of the result stored under `base_key`, from the line at `cursor` on.
+// Each line is sent as an event with its index as id, so reconnecting
+// browsers tell us the last one they got in `Last-Event-ID`. Once the run
+// is done, the stream ends with a `result` event telling the status.
+//
+// Iron copies the body of the response into the connection as it's read,
+// so reading blocks until there is something new to send.
+pub struct LogStream {
+    base_key: String,
+    cursor: isize,
+    lease: Option<Lease>,
+    redis: Option<redis::Connection>,
+    polls: u32,
+    done: bool,
+    pending: Vec<u8>,
+}
+
+impl LogStream {
+    pub fn new(base_key: &str, cursor: isize) -> LogStream {
+        LogStream {
+            base_key: base_key.to_owned(),
+            cursor: cursor,
+            lease: Lease::take(),
+            redis: None,
+            polls: 0,
+            done: false,
+            pending: vec![],
+        }
+    }
+
+    // ### Lines
+    // The lines logged since the cursor
+    fn lines(&self, redis: &redis::Connection) -> Vec<String> {
+        let lines: RedisResult<Value> = redis.lrange(format!("log/{}", self.base_key), self.cursor, -1);
+        match lines {
+            Ok(Value::Bulk(lines)) => {
+                lines.iter()
+                     .filter_map(|line| {
+                         match *line {
+                             Value::Data(ref line) => Some(String::from_utf8_lossy(line).into_owned()),
+                             _ => None,
+                         }
+                     })
+                     .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    // ### Poll
+    // Queue the events for everything new since the last poll
+    fn poll(&mut self) {
+        if self.redis.is_none() {
+            self.redis = Some(setup_redis());
+        }
+        // without a lease, a single look at the log it is
+        if self.lease.is_none() {
+            self.pending.extend_from_slice(format!("retry: {}\n\n", RETRY_MILLIS).as_bytes());
+            self.done = true;
+        }
+        let (record, lines) = {
+            let redis = self.redis.as_ref().unwrap();
+            // Look at the record before the log, so we don't miss
+            // the last lines written right before the run finished
+            let record = get_record_or(redis, &self.base_key, || {});
+            (record, self.lines(redis))
+        };
+        for line in lines {
+            let cursor = self.cursor;
+            event(&mut self.pending, None, Some(cursor), &line);
+            self.cursor += 1;
+        }
+        if record.is_done() {
+            let verdict = match record.message {
+                Some(ref message) => format!("{}: {}", record.text(), message),
+                None => record.text(),
+            };
+            event(&mut self.pending, Some("result"), None, &verdict);
+            self.done = true;
+        } else if self.polls % KEEP_ALIVE_POLLS == 0 {
+            self.pending.extend_from_slice(b": linting\n\n");
+        }
+        self.polls += 1;
+    }
+}
+
+// ## Event
+// Queue a single event. Fields can't span lines, so every line of the
+// data gets a field of its own.
+fn event(out: &mut Vec<u8>, name: Option<&str>, id: Option<isize>, data: &str) {
+    if let Some(name) = name {
+        out.extend_from_slice(format!("event: {}\n", name).as_bytes());
+    }
+    if let Some(id) = id {
+        out.extend_from_slice(format!("id: {}\n", id).as_bytes());
+    }
+    for line in data.lines() {
+        out.extend_from_slice(format!("data: {}\n", line).as_bytes());
+    }
+    out.push(b'\n');
+}
+
+impl Read for LogStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pending.is_empty() {
+            while self.pending.is_empty() {
+                if self.done || self.polls >= MAX_POLLS {
+                    return Ok(0);
+                }
+                self.poll();
+                if self.pending.is_empty() {
+                    thread::sleep(Duration::from_millis(POLL_MILLIS));
+                }
+            }
+            // The connection is buffered with the same size as what we are
+            // asked to fill and is never flushed in between – only writes
+            // filling the buffer go out right away. So we pad the events
+            // with a comment, for them to reach the browser now.
+            let padding = match self.pending.len() % buf.len() {
+                0 => 0,
+                rest if buf.len() - rest < 2 => 2 * buf.len() - rest,
+                rest => buf.len() - rest,
+            };
+            if padding > 0 {
+                self.pending.push(b':');
+                self.pending.extend(iter::repeat(b' ').take(padding - 2));
+                self.pending.push(b'\n');
+            }
+        }
+        let length = cmp::min(buf.len(), self.pending.len());
+        buf[..length].copy_from_slice(&self.pending[..length]);
+        self.pending.drain(..length);
+        Ok(length)
+    }
+}
@@ -0,0 +1,47 @@
+// The threads of the server and the ones requests may keep busy for long
+
+use std::cmp;
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+// How many threads serve requests, unless `CLIPPY_THREADS` says otherwise
+static DEFAULT_THREADS: usize = 16;
+
+static BUSY: AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+    static ref THREADS: usize = env::var("CLIPPY_THREADS")
+                                    .ok()
+                                    .and_then(|threads| threads.parse::<usize>().ok())
+                                    .map(|threads| cmp::max(threads, 2))
+                                    .unwrap_or(DEFAULT_THREADS);
+}
+
+// ## Count
+// How many threads serve requests
+pub fn count() -> usize {
+    *THREADS
+}
+
+// ## Lease
+// Following a log or waiting for a result keeps a thread busy for as long
+// as it takes. Those requests share half of the threads, so there are always
+// some left for everyone else. The lease is given back once it's dropped.
+pub struct Lease;
+
+impl Lease {
+    pub fn take() -> Option<Lease> {
+        if BUSY.fetch_add(1, Ordering::SeqCst) < count() / 2 {
+            Some(Lease)
+        } else {
+            BUSY.fetch_sub(1, Ordering::SeqCst);
+            None
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        BUSY.fetch_sub(1, Ordering::SeqCst);
+    }
+}
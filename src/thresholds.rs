@@ -0,0 +1,111 @@
+// Map the number of warnings to the color of the badge
+
+extern crate rustc_serialize;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::vec::Vec;
+
+use rustc_serialize::json::Json;
+
+// Without any configuration, no warnings are green, any are yellow
+static DEFAULT_THRESHOLDS: &'static str = "0:brightgreen,yellow";
+
+// The file, next to the `Cargo.toml`, projects can configure the service in
+static CONFIG_FILE: &'static str = "clippy-service.json";
+
+// ## Thresholds
+// A list of `(up to N warnings, color)` steps, checked in order, and the
+// color to use if none of them matches. They are written down as
+// `5:brightgreen,20:yellow,orange`, meaning up to five warnings are green,
+// up to 20 yellow and anything above orange.
+pub struct Thresholds {
+    steps: Vec<(u32, String)>,
+    otherwise: String,
+}
+
+impl Thresholds {
+    // ### Parse
+    // Read thresholds from their written down form
+    pub fn parse(spec: &str) -> Result<Thresholds, String> {
+        let mut steps: Vec<(u32, String)> = vec![];
+        let mut otherwise: Option<String> = None;
+        for part in spec.split(',').map(|part| part.trim()) {
+            if otherwise.is_some() {
+                return Err(format!("Nothing may follow the final color: {}", part));
+            }
+            let step: Vec<&str> = part.splitn(2, ':').collect();
+            match step.len() {
+                2 => {
+                    match step[0].trim().parse::<u32>() {
+                        Ok(limit) => steps.push((limit, step[1].trim().to_owned())),
+                        Err(_) => return Err(format!("Not a number of warnings: {}", step[0])),
+                    }
+                }
+                _ if !part.is_empty() => otherwise = Some(part.to_owned()),
+                _ => return Err(String::from("Empty threshold")),
+            }
+        }
+        match otherwise {
+            Some(otherwise) => {
+                Ok(Thresholds {
+                    steps: steps,
+                    otherwise: otherwise,
+                })
+            }
+            None => Err(String::from("The color for everything above the thresholds is missing")),
+        }
+    }
+
+    // ### Global
+    // The thresholds configured for the whole service through the
+    // `CLIPPY_WARNING_COLORS` environment variable, or the defaults.
+    pub fn global() -> Thresholds {
+        env::var("CLIPPY_WARNING_COLORS")
+            .ok()
+            .and_then(|spec| {
+                Thresholds::parse(&spec)
+                    .map_err(|error| warn!("Invalid CLIPPY_WARNING_COLORS: {}", error))
+                    .ok()
+            })
+            .unwrap_or(Thresholds::parse(DEFAULT_THRESHOLDS).unwrap())
+    }
+
+    // ### For Project
+    // Projects may override the global thresholds with the `warning_colors`
+    // entry in their `clippy-service.json`.
+    pub fn for_project<F>(path: &Path, logger: &F) -> Thresholds
+        where F: Fn(&str)
+    {
+        let mut content = String::new();
+        if File::open(path.join(CONFIG_FILE)).and_then(|mut f| f.read_to_string(&mut content)).is_err() {
+            return Thresholds::global();
+        }
+        let spec = Json::from_str(&content)
+                       .ok()
+                       .and_then(|config| config.find("warning_colors").and_then(|c| c.as_string()).map(String::from));
+        match spec.map(|spec| Thresholds::parse(&spec)) {
+            Some(Ok(thresholds)) => {
+                logger(&format!("Using warning colors from {}", CONFIG_FILE));
+                thresholds
+            }
+            Some(Err(error)) => {
+                logger(&format!("Ignoring warning colors of {}: {}", CONFIG_FILE, error));
+                Thresholds::global()
+            }
+            None => Thresholds::global(),
+        }
+    }
+
+    // ### Color
+    // The color for the given number of warnings
+    pub fn color(&self, warnings: u32) -> String {
+        self.steps
+            .iter()
+            .find(|&&(limit, _)| warnings <= limit)
+            .map(|&(_, ref color)| color.clone())
+            .unwrap_or(self.otherwise.clone())
+    }
+}
@@ -0,0 +1,244 @@
+// The variants of badges we offer and what each of them shows
+
+extern crate redis;
+
+use helpers::get_diagnostics;
+use diagnostics::{Diagnostic, Kind};
+use lints::{CATEGORIES, lint_groups, breakdown};
+use record::{ClippyRecord, State};
+
+// ## Badge Context
+// Everything a renderer may need to know about the result it is showing:
+// the record, the stored diagnostics – loaded only if the renderer asks for
+// them – and the argument of variants showing a part of the result, like
+// the category or lint.
+pub struct BadgeContext<'a> {
+    pub redis: &'a redis::Connection,
+    pub base_key: &'a str,
+    pub record: &'a ClippyRecord,
+    pub argument: Option<&'a str>,
+}
+
+impl<'a> BadgeContext<'a> {
+    pub fn diagnostics(&self) -> Option<Vec<Diagnostic>> {
+        get_diagnostics(self.redis, &format!("diagnostics/{}", self.base_key))
+    }
+}
+
+// ## Badge Renderer
+// A variant of the badge, requested by its `name` as method, e.g. `emojibadge.svg`.
+// It decides the label, text and color of the badge, drawing it is up to
+// `badge_response` – or returns why it can't, like for an unknown category.
+// All requests share the renderers, so they have to be `Sync`.
+pub trait BadgeRenderer: Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    // What the `argument` is, if the variant takes one
+    fn argument(&self) -> Option<&'static str> {
+        None
+    }
+    fn render(&self, context: &BadgeContext) -> Result<(String, String, String), String>;
+}
+
+// ## Registry
+// All the variants we offer. To add a new one, implement `BadgeRenderer`
+// and register it here. They are set up once, for all requests to share.
+lazy_static! {
+    static ref REGISTRY: Vec<Box<BadgeRenderer>> = vec![
+        Box::new(TextBadge),
+        Box::new(EmojiBadge {
+            name: "emojibadge",
+            description: "The status told with emoji",
+            label: "clippy",
+            emoji: &DEFAULT_EMOJI,
+        }),
+        Box::new(EmojiBadge {
+            name: "fullemojibadge",
+            description: "The status told with emoji, labelled with a paperclip",
+            label: "📎",
+            emoji: &DEFAULT_EMOJI,
+        }),
+        Box::new(CountBadge),
+        Box::new(CategoryBadge),
+        Box::new(LintBadge),
+    ];
+}
+
+pub fn registry() -> &'static [Box<BadgeRenderer>] {
+    &REGISTRY
+}
+
+// ## Find
+// The variant of the given name, if there is one
+pub fn find(name: &str) -> Option<&'static BadgeRenderer> {
+    registry().iter().find(|renderer| renderer.name() == name).map(|renderer| &**renderer)
+}
+
+// ## Text Badge
+// The status as text, in the color of the result: our classic badge
+struct TextBadge;
+
+impl BadgeRenderer for TextBadge {
+    fn name(&self) -> &'static str {
+        "badge"
+    }
+    fn description(&self) -> &'static str {
+        "The status as text, e.g. `3 warnings`"
+    }
+    fn render(&self, context: &BadgeContext) -> Result<(String, String, String), String> {
+        Ok((String::from("clippy"), context.record.text(), context.record.color.clone()))
+    }
+}
+
+// ## Emoji Badge
+// The status told with emoji, replacing the words of the text. Which emoji
+// those are, is up to the set given.
+pub struct EmojiSet {
+    pub linting: &'static str,
+    pub failed: &'static str,
+    pub build_failed: &'static str,
+    pub ice: &'static str,
+    pub success: &'static str,
+    pub errors: &'static str,
+    pub warnings: &'static str,
+}
+
+pub static DEFAULT_EMOJI: EmojiSet = EmojiSet {
+    linting: "👷",
+    failed: "😱",
+    build_failed: "💥",
+    ice: "🧊",
+    success: "👌",
+    errors: "🤕",
+    warnings: "😟",
+};
+
+struct EmojiBadge {
+    name: &'static str,
+    description: &'static str,
+    label: &'static str,
+    emoji: &'static EmojiSet,
+}
+
+impl BadgeRenderer for EmojiBadge {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn description(&self) -> &'static str {
+        self.description
+    }
+    fn render(&self, context: &BadgeContext) -> Result<(String, String, String), String> {
+        let record = context.record;
+        let text = match record.state {
+            State::Linting => self.emoji.linting.to_owned(),
+            State::Failed => self.emoji.failed.to_owned(),
+            State::BuildFailed => self.emoji.build_failed.to_owned(),
+            State::Ice => self.emoji.ice.to_owned(),
+            State::Success if record.baselined == 0 => self.emoji.success.to_owned(),
+            _ => record.text().replace("errors", self.emoji.errors).replace("warnings", self.emoji.warnings),
+        };
+        Ok((self.label.to_owned(), text, record.color.clone()))
+    }
+}
+
+// ## Count Badge
+// Just the number of warnings and errors found – for where space is short
+struct CountBadge;
+
+impl BadgeRenderer for CountBadge {
+    fn name(&self) -> &'static str {
+        "countbadge"
+    }
+    fn description(&self) -> &'static str {
+        "The number of warnings and errors found"
+    }
+    fn render(&self, context: &BadgeContext) -> Result<(String, String, String), String> {
+        let record = context.record;
+        let text = match record.state {
+            State::Success | State::Warnings | State::LintErrors => {
+                (record.warnings + record.errors).to_string()
+            }
+            _ => record.text(),
+        };
+        Ok((String::from("clippy"), text, record.color.clone()))
+    }
+}
+
+// ## Category Badge
+// How many lints of the category given were found. Correctness lints
+// are most likely actual bugs, so these are red.
+struct CategoryBadge;
+
+impl BadgeRenderer for CategoryBadge {
+    fn name(&self) -> &'static str {
+        "categorybadge"
+    }
+    fn description(&self) -> &'static str {
+        "The number of lints of a category found, at `badge/:category`"
+    }
+    fn argument(&self) -> Option<&'static str> {
+        Some("category")
+    }
+    fn render(&self, context: &BadgeContext) -> Result<(String, String, String), String> {
+        let category = context.argument.unwrap_or("other");
+        if category != "other" && !CATEGORIES.contains(&category) {
+            return Err(format!("Unknown category {}", category));
+        }
+        let label = format!("clippy {}", category);
+        Ok(match context.diagnostics() {
+            // A lint we can't place might well be of this category,
+            // so we can't tell how many there are – surely not zero.
+            Some(diagnostics) => {
+                let breakdown = breakdown(&diagnostics, lint_groups());
+                let count = breakdown.categories.get(category).cloned().unwrap_or(0);
+                match count {
+                    _ if !breakdown.complete() => (label, String::from("unknown"), String::from("lightgrey")),
+                    0 => (label, count.to_string(), String::from("brightgreen")),
+                    _ if category == "correctness" => (label, count.to_string(), String::from("red")),
+                    _ => (label, count.to_string(), String::from("yellow")),
+                }
+            }
+            None => (label, context.record.text(), context.record.color.clone()),
+        })
+    }
+}
+
+// ## Lint Badge
+// How often a single lint was found. If it was denied, it's an error
+// and thus red.
+struct LintBadge;
+
+impl BadgeRenderer for LintBadge {
+    fn name(&self) -> &'static str {
+        "lintbadge"
+    }
+    fn description(&self) -> &'static str {
+        "How often a single lint was found, at `badge?lint=:lint`"
+    }
+    fn argument(&self) -> Option<&'static str> {
+        Some("lint")
+    }
+    fn render(&self, context: &BadgeContext) -> Result<(String, String, String), String> {
+        let lint = match context.argument {
+            Some(lint) => lint,
+            None => return Err(String::from("Which lint? Missing the `lint` parameter")),
+        };
+        let label = format!("clippy {}", lint);
+        Ok(match context.diagnostics() {
+            Some(diagnostics) => {
+                let found: Vec<&Diagnostic> = diagnostics.iter()
+                    .filter(|d| d.lint.as_ref().map(|l| l.as_str()) == Some(lint))
+                    .collect();
+                (label, found.len().to_string(), String::from(
+                    if found.is_empty() {
+                        "brightgreen"
+                    } else if found.iter().any(|d| d.kind() == Kind::LintError) {
+                        "red"
+                    } else {
+                        "yellow"
+                    }))
+            }
+            None => (label, context.record.text(), context.record.color.clone()),
+        })
+    }
+}
@@ -0,0 +1,77 @@
+// Let requests wait for a result to come in, rather than asking again and again
+
+extern crate time;
+
+use std::cmp;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use time::precise_time_ns;
+
+use threads::Lease;
+
+// No one waits longer than this, whatever they ask for
+pub static MAX_WAIT_SECONDS: u64 = 120;
+
+// Results may come in through another instance of the service, which we
+// don't hear about, so waiting requests look again every so often.
+static RECHECK_MILLIS: u64 = 2000;
+
+// ## Results
+// How many results came in so far. Counting them lets a request tell,
+// whether one came in while it was looking for its own.
+lazy_static! {
+    static ref RESULTS: (Mutex<u64>, Condvar) = (Mutex::new(0), Condvar::new());
+}
+
+// The count stays right even if a thread panicked while holding the lock,
+// so we carry on with it.
+fn lock() -> MutexGuard<'static, u64> {
+    RESULTS.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// ## Notify
+// A result came in, wake up everyone waiting to look whether it's theirs
+pub fn notify() {
+    *lock() += 1;
+    RESULTS.1.notify_all();
+}
+
+// ## Wait For
+// Wait up to `seconds` for `check` to find what we are waiting for, looking
+// again whenever a result comes in. If it can't be found in time – or no
+// thread can be spared to wait – this is `None`.
+pub fn wait_for<T, F>(seconds: u64, check: F) -> Option<T>
+    where F: Fn() -> Option<T>
+{
+    let _lease = match Lease::take() {
+        Some(lease) => lease,
+        None => return check(),
+    };
+    let mut results = *lock();
+
+    let deadline = precise_time_ns() + cmp::min(seconds, MAX_WAIT_SECONDS) * 1_000_000_000;
+    let mut found = check();
+    while found.is_none() {
+        let now = precise_time_ns();
+        if now >= deadline {
+            break;
+        }
+        let timeout = cmp::min((deadline - now) / 1_000_000, RECHECK_MILLIS);
+        {
+            let latest = lock();
+            // only sleep, if no result came in since we last looked
+            results = if *latest == results {
+                let (latest, _) = match RESULTS.1.wait_timeout(latest, Duration::from_millis(timeout)) {
+                    Ok(woken) => woken,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                *latest
+            } else {
+                *latest
+            };
+        }
+        found = check();
+    }
+    found
+}